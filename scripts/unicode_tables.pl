@@ -0,0 +1,101 @@
+#!/usr/bin/env perl
+# Generates src/unicode_tables.rs from the Unicode Character Database bundled with Perl.
+#
+# usage: perl scripts/unicode_tables.pl > src/unicode_tables.rs
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_invlist prop_values prop_value_aliases prop_aliases all_casefolds);
+
+# [Rust name, Perl property]
+my @tables = (
+    ["PERL_DIGIT", "Digit"],
+    ["PERL_SPACE", "White_Space"],
+    ["PERL_WORD", "Word"],
+);
+
+# POSIX bracket expressions, with both the ASCII and the Unicode definitions
+for my $name (qw(Alnum Alpha Blank Cntrl Digit Graph Lower Print Punct Space Upper Word XDigit)) {
+    push @tables, ["POSIX_" . uc($name), "Posix$name"], ["XPOSIX_" . uc($name), "XPosix$name"];
+}
+
+# the classes `\p{...}` can name: [lookup table name, [[Rust name, Perl property, aliases...], ...]]
+my @lookups = (
+    ["GENERAL_CATEGORY", [map { ["GC_" . uc($_), "gc=$_", prop_value_aliases("gc", $_)] } prop_values("gc")]],
+    # Katakana_Or_Hiragana has no aliases as it is only used by Script_Extensions
+    ["SCRIPT", [map { ["SC_" . uc($_), "sc=$_", prop_value_aliases("sc", $_)] } grep { prop_value_aliases("sc", $_) } prop_values("sc")]],
+    ["BINARY_PROPERTY", [map { ["PROP_" . uc($_), $_, prop_aliases($_)] } qw(Alpha Any ASCII Assigned Emoji Lower Upper WSpace)]],
+);
+
+# turns an inversion list into inclusive ranges of scalar values, skipping the surrogates
+sub ranges {
+    my @invlist = @_;
+    my @ranges;
+
+    for (my $i = 0; $i < @invlist; $i += 2) {
+        my $start = $invlist[$i];
+        my $end = $i + 1 < @invlist ? $invlist[$i + 1] - 1 : 0x10FFFF;
+
+        for my $range ([$start, $end < 0xD7FF ? $end : 0xD7FF], [$start > 0xE000 ? $start : 0xE000, $end]) {
+            push @ranges, $range if $range->[0] <= $range->[1];
+        }
+    }
+
+    return @ranges;
+}
+
+sub print_table {
+    my ($name, $property) = @_;
+    my @ranges = ranges(prop_invlist($property));
+
+    print "\npub const $name: &[(char, char)] = &[\n";
+    while (my @line = splice(@ranges, 0, 4)) {
+        print "    ", join(" ", map { sprintf("('\\u{%X}', '\\u{%X}'),", @$_) } @line), "\n";
+    }
+    print "];\n";
+}
+
+printf "// DO NOT EDIT: generated by scripts/unicode_tables.pl from Unicode %s\n", Unicode::UCD::UnicodeVersion();
+
+for my $table (@tables) {
+    print_table(@$table);
+}
+
+for my $lookup (@lookups) {
+    my ($lookup_name, $entries) = @$lookup;
+
+    for my $entry (@$entries) {
+        print_table($entry->[0], $entry->[1]);
+    }
+
+    print "\npub const $lookup_name: &[(&str, &[(char, char)])] = &[\n";
+    for my $entry (@$entries) {
+        my ($name, $property, @aliases) = @$entry;
+        my %seen;
+        print "    ", join(" ", map { "(\"$_\", $name)," } grep { !$seen{$_}++ } @aliases), "\n";
+    }
+    print "];\n";
+}
+
+# simple case folding, as the other chars folding to the same char as each char does
+my %orbits;
+my $casefolds = all_casefolds();
+for my $code (sort { $a <=> $b } keys %$casefolds) {
+    my $simple = $casefolds->{$code}{simple};
+    next if $simple eq "";
+
+    push @{$orbits{hex($simple)}}, hex($simple) unless $orbits{hex($simple)};
+    push @{$orbits{hex($simple)}}, $code;
+}
+
+my %others;
+for my $orbit (values %orbits) {
+    for my $c (@$orbit) {
+        $others{$c} = [sort { $a <=> $b } grep { $_ != $c } @$orbit];
+    }
+}
+
+print "\npub const CASE_FOLDING: &[(char, &[char])] = &[\n";
+for my $c (sort { $a <=> $b } keys %others) {
+    printf "    ('\\u{%X}', &[%s]),\n", $c, join(", ", map { sprintf("'\\u{%X}'", $_) } @{$others{$c}});
+}
+print "];\n";
@@ -0,0 +1,154 @@
+use crate::unicode::*;
+
+/**
+ * a set of chars held as sorted, non-overlapping and non-adjacent ranges, so that a class as large as
+ * `[^a]` or `\p{Han}` costs a handful of ranges rather than every char in it
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Class {
+    ranges: Vec<(char, char)>
+}
+
+/**
+ * @return: the char right after c, skipping the surrogates, or None for char::MAX
+ */
+pub fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => char::from_u32(c as u32 + 1)
+    }
+}
+
+/**
+ * @return: the char right before c, skipping the surrogates, or None for '\0'
+ */
+pub fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        c => (c as u32).checked_sub(1).and_then(char::from_u32)
+    }
+}
+
+impl Class {
+    /**
+     * @param ranges: ranges of chars in any order, where the ranges ending before they start are empty
+     */
+    pub fn new(ranges: impl IntoIterator<Item = (char, char)>) -> Class {
+        let mut sorted = ranges.into_iter().filter(|&(start, end)| start <= end).collect::<Vec<_>>();
+        sorted.sort();
+
+        let mut ranges: Vec<(char, char)> = Vec::with_capacity(sorted.len());
+        for (start, end) in sorted {
+            match ranges.last_mut() {
+                // merges the range overlapping or adjoining the last one
+                Some(last) if next_char(last.1).is_none_or(|next| start <= next) => last.1 = last.1.max(end),
+                _ => ranges.push((start, end))
+            }
+        }
+
+        Class { ranges }
+    }
+
+    pub fn char(c: char) -> Class {
+        Class { ranges: vec![(c, c)] }
+    }
+
+    pub fn from_table(table: &[(char, char)]) -> Class {
+        Class::new(table.iter().cloned())
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        contains(&self.ranges, c)
+    }
+
+    pub fn complement(&self) -> Class {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut start = Some('\0');
+
+        for &(range_start, range_end) in self.ranges.iter() {
+            if let Some(start) = start.filter(|&start| start < range_start) {
+                ranges.push((start, prev_char(range_start).unwrap()));
+            }
+            start = next_char(range_end);
+        }
+        if let Some(start) = start {
+            ranges.push((start, char::MAX));
+        }
+
+        Class { ranges }
+    }
+
+    pub fn union(&self, other: &Class) -> Class {
+        Class::new(self.ranges.iter().chain(other.ranges.iter()).cloned())
+    }
+
+    pub fn intersection(&self, other: &Class) -> Class {
+        self.complement().union(&other.complement()).complement()
+    }
+
+    pub fn difference(&self, other: &Class) -> Class {
+        self.intersection(&other.complement())
+    }
+
+    /**
+     * @return: the chars matching a char of the class regardless of case
+     */
+    pub fn case_fold(&self) -> Class {
+        let variants = CASE_FOLDING.iter()
+            .filter(|&&(c, _)| self.contains(c))
+            .flat_map(|&(_, variants)| variants.iter().map(|&c| (c, c)));
+        Class::new(self.ranges.iter().cloned().chain(variants))
+    }
+
+    /**
+     * @return: the chars of the class whose case variants are all in the class, i.e. the complement of
+     *          case_fold of the complement, so that negation and case folding commute
+     */
+    pub fn case_interior(&self) -> Class {
+        let outside = CASE_FOLDING.iter()
+            .filter(|&&(c, variants)| self.contains(c) && variants.iter().any(|&c| !self.contains(c)))
+            .map(|&(c, _)| (c, c));
+        self.difference(&Class::new(outside))
+    }
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[")?;
+        for &(start, end) in self.ranges.iter() {
+            if start == end {
+                write!(f, "{}", start.escape_debug())?;
+            }
+            else {
+                write!(f, "{}-{}", start.escape_debug(), end.escape_debug())?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_operations() {
+        let class = Class::new([('d', 'f'), ('a', 'b'), ('c', 'c'), ('x', 'z'), ('y', 'y')]);
+        assert_eq!(class.ranges(), &[('a', 'f'), ('x', 'z')]);
+
+        // the surrogates are not chars, so the chars around them adjoin
+        assert_eq!(Class::new([('\u{D000}', '\u{D7FF}'), ('\u{E000}', '\u{E0FF}')]).ranges(), &[('\u{D000}', '\u{E0FF}')]);
+        assert_eq!(Class::char('\u{D7FF}').complement().ranges(), &[('\0', '\u{D7FE}'), ('\u{E000}', char::MAX)]);
+
+        assert_eq!(class.complement().complement(), class);
+        assert_eq!(Class::new([]).complement().ranges(), &[('\0', char::MAX)]);
+        assert_eq!(class.intersection(&Class::new([('e', 'y')])).ranges(), &[('e', 'f'), ('x', 'y')]);
+        assert_eq!(class.difference(&Class::new([('b', 'e')])).ranges(), &[('a', 'a'), ('f', 'f'), ('x', 'z')]);
+        assert_eq!(Class::new([('a', 'c')]).case_fold().ranges(), &[('A', 'C'), ('a', 'c')]);
+        assert_eq!(Class::new([('a', 'c'), ('A', 'B')]).case_interior().ranges(), &[('A', 'B'), ('a', 'b')]);
+    }
+}
@@ -1,37 +1,48 @@
 use crate::nfa::*;
+use crate::look::*;
+use crate::class::*;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::cell::RefCell;
 
 pub const DEAD_STATE: usize = usize::MAX;
 
 struct State {
-    transitions: HashMap<char, usize>,
-    default_transition: usize,
+    // sorted, non-overlapping ranges of chars and the states they lead to, where the chars out of
+    // the ranges lead to the dead state
+    transitions: Vec<(char, char, usize)>,
 }
 
 pub struct DFA {
     states: HashMap<usize, State>,
-    start: usize,
-    accept: Vec<usize>,
+    // the start state for each kind of char preceding the search
+    start: HashMap<Adjacent, usize>,
+    // the states accepting when followed by each kind of char
+    accept: HashSet<(usize, Adjacent)>,
+    // the kinds of chars the assertions tell apart
+    adjacents: Vec<Adjacent>,
 }
 
 pub trait DFAExt {
-    fn new(nfa: NFA) -> Self;
-    fn is_accept(&self, state: usize) -> bool;
+    /**
+     * @param size_limit: the maximum number of states to build from the NFA
+     * @return: the DFA, or None if it exceeds the limit
+     */
+    fn new(nfa: NFA, size_limit: usize) -> Option<Self> where Self: Sized;
+    fn is_accept(&self, state: usize, next: Option<char>) -> bool;
     fn is_dead(&self, state: usize) -> bool;
     fn transition(&self, c: char, current_state: usize) -> usize;
-    fn get_start(&self) -> usize;
+    fn get_start(&self, prev: Option<char>) -> usize;
 }
 
 
 impl DFAExt for DFA {
-    fn new(nfa: NFA) -> Self {
-        From::from(nfa)
+    fn new(nfa: NFA, size_limit: usize) -> Option<Self> {
+        DFA::from_nfa(nfa, size_limit)
     }
 
-    fn is_accept(&self, state: usize) -> bool {
-        self.accept.contains(&state)
+    fn is_accept(&self, state: usize, next: Option<char>) -> bool {
+        self.accept.contains(&(state, self.adjacent(next)))
     }
 
     fn is_dead(&self, state: usize) -> bool {
@@ -39,18 +50,11 @@ impl DFAExt for DFA {
     }
 
     fn transition(&self, c: char, current_state: usize) -> usize {
-        let next_state = self.get_state(current_state).get_transition(c);
-
-        if let Some(&state_num) = next_state {
-            state_num
-        }
-        else {
-            self.get_state(current_state).default_transition
-        }
+        self.get_state(current_state).get_transition(c).unwrap_or(DEAD_STATE)
     }
 
-    fn get_start(&self) -> usize {
-        self.start
+    fn get_start(&self, prev: Option<char>) -> usize {
+        *self.start.get(&self.adjacent(prev)).unwrap()
     }
 }
 
@@ -58,122 +62,146 @@ impl DFAExt for DFA {
 impl State {
     pub fn new() -> State {
         State {
-            transitions: HashMap::new(),
-            default_transition: DEAD_STATE,
+            transitions: Vec::new(),
         }
     }
 
-    pub fn add_transition(&mut self, c: char, state_num: usize) {
-        self.transitions.insert(c, state_num);
-    }
+    // merges the range into the one right before it when they lead to the same state
+    pub fn add_transition(&mut self, start: char, end: char, state_num: usize) {
+        let i = self.transitions.partition_point(|&(s, _, _)| s < start);
 
-    pub fn get_transition(&self, c: char) -> Option<&usize> {
-        self.transitions.get(&c)
+        match i.checked_sub(1).map(|i| &mut self.transitions[i]) {
+            Some(prev) if prev.2 == state_num && next_char(prev.1) == Some(start) => prev.1 = end,
+            _ => self.transitions.insert(i, (start, end, state_num))
+        }
     }
 
-    pub fn set_default_transition(&mut self, state_num: usize) {
-        self.default_transition = state_num;
+    pub fn get_transition(&self, c: char) -> Option<usize> {
+        let i = self.transitions.partition_point(|&(_, end, _)| end < c);
+        self.transitions.get(i).filter(|&&(start, _, _)| start <= c).map(|&(_, _, state_num)| state_num)
     }
 }
 
 impl DFA {
-    pub fn add_state(&mut self, state_num: usize) {
-        self.states.insert(state_num, State::new());
-    }
+    fn empty(adjacents: &[Adjacent]) -> DFA {
+        let mut dfa = DFA{
+            states: HashMap::new(),
+            start: HashMap::new(),
+            accept: HashSet::new(),
+            adjacents: adjacents.to_vec(),
+        };
 
-    pub fn add_transition(&mut self, state_num: usize, c: char, next_state: usize) {
-        self.states.get_mut(&state_num).unwrap().add_transition(c, next_state);
+        dfa.add_state(DEAD_STATE);
+
+        dfa
     }
 
-    pub fn add_default_transition(&mut self, state_num: usize, next_state: usize) {
-        self.states.get_mut(&state_num).unwrap().set_default_transition(next_state);
+    pub fn add_state(&mut self, state_num: usize) {
+        self.states.insert(state_num, State::new());
     }
 
-    pub fn set_start(&mut self, state_num: usize) {
-        self.start = state_num;
+    pub fn add_transition(&mut self, state_num: usize, start: char, end: char, next_state: usize) {
+        self.states.get_mut(&state_num).unwrap().add_transition(start, end, next_state);
     }
 
-    pub fn add_accept(&mut self, state_num: usize) {
-        self.accept.push(state_num);
+    pub fn set_start(&mut self, prev: Adjacent, state_num: usize) {
+        self.start.insert(prev, state_num);
     }
 
-    pub fn get_start(&self) -> usize {
-        self.start
+    pub fn add_accept(&mut self, state_num: usize, next: Adjacent) {
+        self.accept.insert((state_num, next));
     }
 
     fn get_state(&self, state_num: usize) -> &State {
         self.states.get(&state_num).unwrap()
     }
 
-    fn state_num_range(&self) -> std::ops::Range<usize> {
-        0..self.states.len()
+    fn adjacent(&self, c: Option<char>) -> Adjacent {
+        adjacent(c, &self.adjacents)
     }
 
+    /**
+     * @param nfa_states: the set of NFA states the DFA state stands for
+     * @param prev: the kind of char consumed right before reaching the state
+     * @return: the DFA state and whether it has been added just now
+     */
+    fn get_or_add_state(&mut self, nfa: &NFA, nfa_to_dfa_state_map: &mut HashMap<(Vec<usize>, Adjacent), usize>, nfa_states: Vec<usize>, prev: Adjacent) -> (usize, bool) {
+        if nfa_states == [DEAD_STATE] {
+            return (DEAD_STATE, false);
+        }
+        else if let Some(&dfa_state_num) = nfa_to_dfa_state_map.get(&(nfa_states.clone(), prev)) {
+            return (dfa_state_num, false);
+        }
+
+        // the dead state is not counted
+        let dfa_state_num = self.states.len() - 1;
+        self.add_state(dfa_state_num);
+
+        // acceptance may depend on assertions about the char following the state
+        for &next in nfa.adjacents() {
+            if nfa.look_closure(&nfa_states, prev, next).contains(&nfa.get_accept()) {
+                self.add_accept(dfa_state_num, next);
+            }
+        }
+
+        nfa_to_dfa_state_map.insert((nfa_states, prev), dfa_state_num);
+
+        (dfa_state_num, true)
+    }
 }
 
 #[allow(dead_code)]
 pub fn print_dfa(dfa: &DFA) {
     println!("digraph DFA {{");
     println!("\tnode [shape=circle]");
-    println!("");
+    println!();
 
     for (state_num, state) in dfa.states.iter() {
-        if dfa.accept.contains(state_num) {
+        if dfa.accept.iter().any(|(accept, _)| accept == state_num) {
             println!("\tn{} [shape=doublecircle]", state_num);
         }
         else if state_num == &DEAD_STATE {
-            ()
         }
         else {
             println!("\tn{} [shape=circle]", state_num);
         }
 
-        for (c, next_state) in state.transitions.iter() {
-            println!("\tn{} -> n{} [label=\"{}\"]", state_num, next_state, c);
-        }
-
-        if state.default_transition != DEAD_STATE {
-            println!("\tn{} -> n{}", state_num, state.default_transition);
+        for &(start, end, next_state) in state.transitions.iter() {
+            if next_state != DEAD_STATE {
+                println!("\tn{} -> n{} [label=\"{}\"]", state_num, next_state, Class::new([(start, end)]).to_string().escape_default());
+            }
         }
     }
 
-    println!("");
+    println!();
     println!("}}");
 }
 
+#[allow(dead_code)]
 pub struct OnTheFlyDFA {
     nfa: NFA,
     dfa: RefCell<DFA>,
-    nfa_to_dfa_state_map: RefCell<HashMap<Vec<usize>, usize>>,
+    nfa_to_dfa_state_map: RefCell<HashMap<(Vec<usize>, Adjacent), usize>>,
 }
 
 impl DFAExt for OnTheFlyDFA {
-    fn new(nfa: NFA) -> Self {
-        let mut dfa = DFA{
-            states: HashMap::new(),
-            start: 0,
-            accept: Vec::new(),
-        };
-
-        dfa.add_state(DEAD_STATE);
-        dfa.add_default_transition(DEAD_STATE, DEAD_STATE);
-
+    // the states are built during the search, at most one per char searched, so the limit is left to the input
+    fn new(nfa: NFA, _size_limit: usize) -> Option<Self> {
+        let mut dfa = DFA::empty(nfa.adjacents());
         let mut nfa_to_dfa_state_map = HashMap::new();
 
-        let dfa_start_num = 0;
-
         // Get the epsilon closure of the NFA start state
         let nfa_start = nfa.epsilon_closure(nfa.get_start());
-        nfa_to_dfa_state_map.insert(nfa_start.clone(), dfa_start_num);
-        nfa_to_dfa_state_map.insert(vec![DEAD_STATE], DEAD_STATE);
-        dfa.add_state(dfa_start_num);
-        dfa.set_start(dfa_start_num);
+        for &prev in nfa.adjacents() {
+            let (dfa_start_num, _) = dfa.get_or_add_state(&nfa, &mut nfa_to_dfa_state_map, nfa_start.clone(), prev);
+            dfa.set_start(prev, dfa_start_num);
+        }
 
-        OnTheFlyDFA {
+        Some(OnTheFlyDFA {
             nfa,
             dfa: RefCell::new(dfa),
             nfa_to_dfa_state_map: RefCell::new(nfa_to_dfa_state_map),
-        }
+        })
     }
 
     fn transition(&self, c: char, current_state: usize) -> usize {
@@ -184,148 +212,88 @@ impl DFAExt for OnTheFlyDFA {
             return DEAD_STATE;
         }
         // already visited
-        else if let Some(&next_state) = dfa.get_state(current_dfa_state_num).get_transition(c) {
+        else if let Some(next_state) = dfa.get_state(current_dfa_state_num).get_transition(c) {
             return next_state;
         }
 
-        let next_dfa_state_num;
         let mut nfa_to_dfa_state_map = self.nfa_to_dfa_state_map.borrow_mut();
-        let current_nfa_states = nfa_to_dfa_state_map.iter().find(|(_, &state_num)| state_num == current_dfa_state_num).unwrap().0;
-        let mut next_nfa_states = Vec::new();
-
-        for &nfa_state_num in current_nfa_states.iter() {
-            let nfa_state = self.nfa.get_state(nfa_state_num).unwrap();
-
-            if let Some(next_nfa_state_num) = nfa_state.get_transition(c) {
-                next_nfa_states.extend(self.nfa.epsilon_closure(*next_nfa_state_num));
-            }
-            else {
-                next_nfa_states.extend(self.nfa.epsilon_closure(nfa_state.default_transition));
-            }
-        }
+        let (current_nfa_states, prev) = nfa_to_dfa_state_map.iter().find(|(_, &state_num)| state_num == current_dfa_state_num).unwrap().0.clone();
 
-        next_nfa_states.sort();
-        next_nfa_states.dedup();
+        let next = self.nfa.adjacent(Some(c));
+        let next_nfa_states = self.nfa.next_states(&current_nfa_states, prev, next, c);
+        let (next_dfa_state_num, _) = dfa.get_or_add_state(&self.nfa, &mut nfa_to_dfa_state_map, next_nfa_states, next);
 
-        if !nfa_to_dfa_state_map.contains_key(&next_nfa_states) {
-            next_dfa_state_num = dfa.state_num_range().end;
-            nfa_to_dfa_state_map.insert(next_nfa_states.clone(), next_dfa_state_num);
-            dfa.add_state(next_dfa_state_num);
-            dfa.add_transition(current_dfa_state_num, c, next_dfa_state_num);
-        }
-        else {
-            next_dfa_state_num = *nfa_to_dfa_state_map.get(&next_nfa_states).unwrap();
-            dfa.add_transition(current_dfa_state_num, c, next_dfa_state_num);
-        }
-
-        if next_nfa_states.contains(&self.nfa.get_accept()) {
-            dfa.add_accept(next_dfa_state_num);
-        }
+        // every char of the run containing c leads to the same state, the dead state included
+        let boundaries = self.nfa.boundaries(&current_nfa_states);
+        let i = boundaries.partition_point(|&b| b <= c);
+        let end = boundaries.get(i).map_or(char::MAX, |&b| prev_char(b).unwrap());
+        dfa.add_transition(current_dfa_state_num, boundaries[i - 1], end, next_dfa_state_num);
 
         next_dfa_state_num
     }
 
-    fn is_accept(&self, state: usize) -> bool {
-        self.dfa.borrow().is_accept(state)
+    fn is_accept(&self, state: usize, next: Option<char>) -> bool {
+        self.dfa.borrow().is_accept(state, next)
     }
 
     fn is_dead(&self, state: usize) -> bool {
         self.dfa.borrow().is_dead(state)
     }
 
-    fn get_start(&self) -> usize {
-        self.dfa.borrow().get_start()
+    fn get_start(&self, prev: Option<char>) -> usize {
+        self.dfa.borrow().get_start(prev)
     }
 }
 
-impl From<NFA> for DFA {
-    fn from(nfa: NFA) -> DFA {
-        let mut dfa = DFA{
-            states: HashMap::new(),
-            start: 0,
-            accept: Vec::new(),
-        };
-
-        dfa.add_state(DEAD_STATE);
-        dfa.add_default_transition(DEAD_STATE, DEAD_STATE);
-
+impl DFA {
+    /**
+     * @brief builds every state reachable from the start by subset construction
+     * @param size_limit: the maximum number of states, as they can grow exponentially in the NFA states like `[ab]*a[ab]{20}`
+     * @return: the DFA, or None once it exceeds the limit
+     */
+    fn from_nfa(nfa: NFA, size_limit: usize) -> Option<DFA> {
+        let mut dfa = DFA::empty(nfa.adjacents());
         let mut nfa_to_dfa_state_map = HashMap::new();
-        let mut dfa_state_num = 0;
+        let mut worklist = Vec::new();
 
         // Get the epsilon closure of the NFA start state
         let nfa_start = nfa.epsilon_closure(nfa.get_start());
-        nfa_to_dfa_state_map.insert(nfa_start.clone(), dfa_state_num);
-        nfa_to_dfa_state_map.insert(vec![DEAD_STATE], DEAD_STATE);
-        dfa.add_state(dfa_state_num);
-        dfa.set_start(dfa_state_num);
-
-        let mut worklist = vec![nfa_start];
-        dfa_state_num += 1;
+        for &prev in nfa.adjacents() {
+            let (dfa_start_num, _) = dfa.get_or_add_state(&nfa, &mut nfa_to_dfa_state_map, nfa_start.clone(), prev);
+            dfa.set_start(prev, dfa_start_num);
+            worklist.push((nfa_start.clone(), prev));
+        }
 
         // Process each set of NFA states in the worklist
-        while !worklist.is_empty() {
-            let current_nfa_states = worklist.pop().unwrap();
-            let current_dfa_state_num = *nfa_to_dfa_state_map.get(&current_nfa_states).unwrap();
-
-            // For each character transition in the current NFA states
-            for c in current_nfa_states.iter().flat_map(|&nfa_state_num| nfa.get_state(nfa_state_num).unwrap().transitions.keys()) {
-                let mut next_nfa_states = Vec::new();
-
-                // Collect the next NFA states for the current character
-                for &nfa_state_num in current_nfa_states.iter() {
-                    let nfa_state = nfa.get_state(nfa_state_num).unwrap();
-                    if let Some(next_nfa_state_num) = nfa_state.get_transition(*c) {
-                        next_nfa_states.extend(nfa.epsilon_closure(*next_nfa_state_num));
-                    }
-                    else {
-                        next_nfa_states.extend(nfa.epsilon_closure(nfa_state.default_transition));
-                    }        
-                }
+        while let Some((current_nfa_states, prev)) = worklist.pop() {
+            let current_dfa_state_num = *nfa_to_dfa_state_map.get(&(current_nfa_states.clone(), prev)).unwrap();
 
-                next_nfa_states.sort();
-                next_nfa_states.dedup();
+            // For each run of chars the current NFA states treat alike
+            let boundaries = nfa.boundaries(&current_nfa_states);
+            for (i, &start) in boundaries.iter().enumerate() {
+                let end = boundaries.get(i + 1).map_or(char::MAX, |&b| prev_char(b).unwrap());
+
+                // Collect the next NFA states for the chars of the run
+                let next = nfa.adjacent(Some(start));
+                let next_nfa_states = nfa.next_states(&current_nfa_states, prev, next, start);
 
                 // If the set of next NFA states is not already mapped to a DFA state
-                if !nfa_to_dfa_state_map.contains_key(&next_nfa_states) {
-                    nfa_to_dfa_state_map.insert(next_nfa_states.clone(), dfa_state_num);
-                    dfa.add_state(dfa_state_num);
-                    worklist.push(next_nfa_states.clone());
-                    dfa_state_num += 1;
+                let (next_dfa_state_num, is_new) = dfa.get_or_add_state(&nfa, &mut nfa_to_dfa_state_map, next_nfa_states.clone(), next);
+                if is_new {
+                    // the dead state is not counted
+                    if dfa.states.len() - 1 > size_limit {
+                        return None;
+                    }
+                    worklist.push((next_nfa_states, next));
                 }
 
-                dfa.add_transition(current_dfa_state_num, *c, *nfa_to_dfa_state_map.get(&next_nfa_states).unwrap());
-            }
-
-            // treat default transitions
-            let mut next_nfa_states = Vec::new();
-
-            // Collect the next NFA states for the current character
-            for &nfa_state_num in current_nfa_states.iter() {
-                next_nfa_states.extend(nfa.epsilon_closure(nfa.get_state(nfa_state_num).unwrap().default_transition));
-            }
-
-            next_nfa_states.sort();
-            next_nfa_states.dedup();
-
-            // If the set of next NFA states is not already mapped to a DFA state
-            if !nfa_to_dfa_state_map.contains_key(&next_nfa_states) {
-                nfa_to_dfa_state_map.insert(next_nfa_states.clone(), dfa_state_num);
-                dfa.add_state(dfa_state_num);
-                worklist.push(next_nfa_states.clone());
-                dfa_state_num += 1;
-            }
-
-            dfa.add_default_transition(current_dfa_state_num, *nfa_to_dfa_state_map.get(&next_nfa_states).unwrap());
-        }
-
-        // Add accept states to the DFA
-        for (nfa_states, dfa_state_num) in nfa_to_dfa_state_map.iter() {
-            if nfa_states.contains(&nfa.get_accept()) {
-                dfa.add_accept(*dfa_state_num);
+                if next_dfa_state_num != DEAD_STATE {
+                    dfa.add_transition(current_dfa_state_num, start, end, next_dfa_state_num);
+                }
             }
         }
 
-        dfa
+        Some(dfa)
     }
 }
 
@@ -339,11 +307,11 @@ mod tests {
     #[test]
     fn count_states() {
         let regex = "[a-zA-Z0-9]".repeat(30);
-        let mut tokens = tokenize(&regex.to_string()).unwrap();
-        let root = parse(&mut tokens).unwrap();
+        let mut tokens = tokenize(&regex).unwrap();
+        let (root, _) = parse(&mut tokens, &Config::default()).unwrap();
         let nfa = build_nfa(root);
-        let dfa = DFA::new(nfa);
+        let dfa = DFA::new(nfa, DEFAULT_DFA_SIZE_LIMIT).unwrap();
 
-        assert_eq!(dfa.states.len(), 1862);
+        assert_eq!(dfa.states.len(), 32);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,104 @@
+use std::ops::Range;
+
+/**
+ * an error in a pattern, each kind carrying the part of the pattern it was found at
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // `(` without the matching `)`
+    UnclosedGroup(Diagnostic),
+    // `)` without the matching `(`
+    UnopenedGroup(Diagnostic),
+    // `[` without the matching `]`
+    UnclosedClass(Diagnostic),
+    // bounds out of order or out of range, e.g. `a{3,2}`
+    BadRange(Diagnostic),
+    // `\` at the end of the pattern
+    DanglingEscape(Diagnostic),
+    // an unknown or malformed escape, e.g. `\q` or `\x{110000}`
+    BadEscape(Diagnostic),
+    // an unknown POSIX class or Unicode property, e.g. `[[:vowel:]]` or `\p{Klingon}`
+    UnknownClass(Diagnostic),
+    // a malformed group or flags, e.g. `(?P<1a>x)` or `(?q)`, or a duplicate group name
+    BadGroup(Diagnostic),
+    // a quantifier with nothing to repeat, e.g. `+a` or `a|?`
+    DanglingQuantifier(Diagnostic),
+    UnexpectedToken(Diagnostic),
+    // the pattern compiles to more NFA states than the size limit
+    TooLarge(Diagnostic),
+    // groups, brackets or counted repetitions nested deeper than the nesting limit
+    TooDeep(Diagnostic)
+}
+
+/**
+ * the pattern an error was found in, the byte range of the offending part and what is wrong with it
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pattern: String,
+    span: Range<usize>,
+    message: String
+}
+
+impl Diagnostic {
+    pub(crate) fn new(pattern: &str, span: Range<usize>, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            pattern: pattern.to_string(),
+            span,
+            message: message.into()
+        }
+    }
+}
+
+impl Error {
+    fn diagnostic(&self) -> &Diagnostic {
+        match self {
+            Error::UnclosedGroup(diagnostic) | Error::UnopenedGroup(diagnostic) | Error::UnclosedClass(diagnostic) |
+            Error::BadRange(diagnostic) | Error::DanglingEscape(diagnostic) | Error::BadEscape(diagnostic) |
+            Error::UnknownClass(diagnostic) | Error::BadGroup(diagnostic) | Error::DanglingQuantifier(diagnostic) |
+            Error::UnexpectedToken(diagnostic) | Error::TooLarge(diagnostic) | Error::TooDeep(diagnostic) => diagnostic
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.diagnostic().pattern
+    }
+
+    /**
+     * @return: the byte range of the offending part of the pattern, which is empty at the end of the pattern
+     */
+    pub fn span(&self) -> Range<usize> {
+        self.diagnostic().span.clone()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.diagnostic().message
+    }
+}
+
+/**
+ * renders the message and the line of the pattern with carets under the offending part, e.g.
+ *
+ * unknown escape sequence \q
+ * ab\qc
+ *   ^^
+ */
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let Diagnostic { pattern, span, message } = self.diagnostic();
+
+        // only the line the span starts at, for verbose patterns spanning several lines
+        let line_start = pattern[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = pattern[span.start..].find('\n').map_or(pattern.len(), |i| span.start + i);
+
+        // tabs are kept so that the carets line up with the pattern
+        let indent = pattern[line_start..span.start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+        let width = pattern[span.start..span.end.min(line_end)].chars().count().max(1);
+
+        writeln!(f, "{}", message)?;
+        writeln!(f, "{}", &pattern[line_start..line_end])?;
+        write!(f, "{}{}", indent, "^".repeat(width))
+    }
+}
+
+impl std::error::Error for Error {}
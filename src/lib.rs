@@ -1,6 +1,14 @@
 
+#![allow(clippy::upper_case_acronyms)]
+
+mod error;
+pub use error::{Error, Diagnostic};
 mod token;
 use token::*;
+mod look;
+mod unicode;
+mod unicode_tables;
+mod class;
 mod parse;
 use parse::*;
 mod nfa;
@@ -8,7 +16,35 @@ use nfa::*;
 mod dfa;
 use dfa::*;
 
-use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/**
+ * @return: the pattern matching s literally, with the meta characters in s escaped
+ */
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        if META_CHARACTERS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/**
+ * @brief checks the pattern without compiling it, going on after each error rather than stopping at the first one
+ * @return: every error in the pattern in the order of their spans, none iff. the pattern compiles with the default configuration
+ */
+pub fn check(pattern: &str) -> Vec<Error> {
+    let (mut tokens, mut errors) = tokenize_all(pattern);
+    errors.extend(parse::check(&mut tokens, &Config::default()));
+    errors.sort_by_key(|e| e.span().start);
+    errors
+}
 
 #[cfg(feature = "on_the_fly")]
 pub type TinyRegex = TinyRegexInner<OnTheFlyDFA>;
@@ -17,7 +53,17 @@ pub type TinyRegex = TinyRegexInner<OnTheFlyDFA>;
 pub type TinyRegex = TinyRegexInner<DFA>;
 
 pub struct TinyRegexInner<T: DFAExt> {
-    dfa: T
+    dfa: T,
+    // kept to track the capture groups within the span the DFA finds
+    nfa: NFA,
+    // the names of the capture groups from group 1, None for the unnamed ones
+    group_names: Arc<Vec<Option<String>>>
+}
+
+pub struct TinyRegexBuilder<T: DFAExt> {
+    regex: String,
+    config: Config,
+    dfa: PhantomData<T>
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -27,31 +73,118 @@ pub struct Match<'a> {
     matched_str: &'a str
 }
 
-impl<T: DFAExt> TinyRegexInner<T> {
-    pub fn new(regex: &str) -> Result<TinyRegexInner<T>, String> {
-        let mut tokens = tokenize(&regex.to_string())?;
-        let root = parse(&mut tokens)?;
+#[derive(PartialEq, Debug, Clone)]
+pub struct Captures<'a> {
+    s: &'a str,
+    group_names: Arc<Vec<Option<String>>>,
+    // the start and the end of group i at 2i and 2i+1, where group 0 is the whole match
+    slots: Vec<Option<usize>>
+}
+
+impl<T: DFAExt> TinyRegexBuilder<T> {
+    pub fn new(regex: &str) -> TinyRegexBuilder<T> {
+        TinyRegexBuilder {
+            regex: regex.to_string(),
+            config: Config::default(),
+            dfa: PhantomData
+        }
+    }
+
+    /**
+     * @brief sets the maximum number of NFA states the pattern may compile to,
+     *        e.g. after expanding counted repetitions like `a{1000}`
+     */
+    pub fn size_limit(&mut self, limit: usize) -> &mut TinyRegexBuilder<T> {
+        self.config.size_limit = limit;
+        self
+    }
+
+    /**
+     * @brief sets the maximum number of DFA states the pattern may compile to (100,000 by default),
+     *        as patterns like `[ab]*a[ab]{20}` need exponentially many of them; the on-the-fly DFA builds its states
+     *        during the search instead
+     */
+    pub fn dfa_size_limit(&mut self, limit: usize) -> &mut TinyRegexBuilder<T> {
+        self.config.dfa_size_limit = limit;
+        self
+    }
+
+    /**
+     * @brief sets the maximum depth groups, brackets and counted repetitions may nest to (250 by default),
+     *        so that a deeply nested pattern is an error rather than a stack overflow
+     */
+    pub fn nest_limit(&mut self, limit: usize) -> &mut TinyRegexBuilder<T> {
+        self.config.nest_limit = limit;
+        self
+    }
+
+    /**
+     * @brief chooses between the Unicode (default) and the ASCII definitions of `\d`, `\w` and `\s`
+     */
+    pub fn unicode(&mut self, yes: bool) -> &mut TinyRegexBuilder<T> {
+        self.config.unicode = yes;
+        self
+    }
+
+    /**
+     * @brief lets `.` match `\n` as the `s` flag does
+     */
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut TinyRegexBuilder<T> {
+        self.config.dot_matches_new_line = yes;
+        self
+    }
+
+    /**
+     * @brief treats `\r\n` as a line terminator as the `R` flag does, so that `.` matches neither `\r` nor `\n`
+     *        and the multi-line `^` and `$` match at `\r` and `\n` but not between them
+     */
+    pub fn crlf(&mut self, yes: bool) -> &mut TinyRegexBuilder<T> {
+        self.config.crlf = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<TinyRegexInner<T>, Error> {
+        let mut tokens = tokenize(&self.regex)?;
+        let (root, group_names) = parse(&mut tokens, &self.config)?;
         let nfa = build_nfa(root);
-        let dfa = T::new(nfa);
+        let dfa = T::new(nfa.clone(), self.config.dfa_size_limit).ok_or_else(|| {
+            let message = format!("pattern is too large: its DFA exceeds the size limit of {} states", self.config.dfa_size_limit);
+            tokens.error(Error::TooLarge, 0..self.regex.len(), message)
+        })?;
 
         Ok(TinyRegexInner {
-            dfa: dfa
+            dfa,
+            nfa,
+            group_names: Arc::new(group_names)
         })
     }
+}
+
+impl<T: DFAExt> TinyRegexInner<T> {
+    pub fn new(regex: &str) -> Result<TinyRegexInner<T>, Error> {
+        TinyRegexBuilder::new(regex).build()
+    }
+
+    pub fn builder(regex: &str) -> TinyRegexBuilder<T> {
+        TinyRegexBuilder::new(regex)
+    }
     /**
      * @brief returns true iff. there is a match anywhere in the given string
      */
     pub fn is_match(&self, s: &str) -> bool {
-        if self.dfa.is_accept(self.dfa.get_start()) {
-            return true
-        }
-        for (i, _) in s.char_indices() {
-            let mut state = self.dfa.get_start();
-            for c in s[i..].chars() {
+        for i in Self::positions(s, 0) {
+            let mut state = self.dfa.get_start(s[..i].chars().next_back());
+            if self.dfa.is_accept(state, s[i..].chars().next()) {
+                return true
+            }
+            for (j, c) in s[i..].char_indices() {
                 state = self.dfa.transition(c, state);
-                if self.dfa.is_accept(state) {
+                if self.dfa.is_accept(state, s[i+j+c.len_utf8()..].chars().next()) {
                     return true
                 }
+                else if self.dfa.is_dead(state) {
+                    break;
+                }
             }
         }
 
@@ -59,98 +192,100 @@ impl<T: DFAExt> TinyRegexInner<T> {
     }
 
     pub fn find<'a>(&self, s: &'a str) -> Option<Match<'a>> {
-        if self.dfa.is_accept(self.dfa.get_start()) {
-            return Some(Match {
-                start: 0,
-                end: 0,
-                matched_str: &s[0..0]
-            })
-        }
+        self.find_at(s, 0)
+    }
+
+    /**
+     * @brief returns the leftmost-longest match starting at or after the byte offset start,
+     *        where the assertions still see the chars before start; an empty match is returned
+     *        only if no longer one starts at the same position, e.g. `a*` finds `aaa` rather than ``
+     */
+    pub fn find_at<'a>(&self, s: &'a str, start: usize) -> Option<Match<'a>> {
+        for i in Self::positions(s, start) {
+            let mut state = self.dfa.get_start(s[..i].chars().next_back());
+            // the empty match, which a longer one found below replaces
+            let mut end = if self.dfa.is_accept(state, s[i..].chars().next()) {
+                Some(i)
+            }
+            else {
+                None
+            };
 
-        for (i, _) in s.char_indices() {
-            let mut state = self.dfa.get_start();
-            let mut is_match = false;
-            let mut end  = i;
             for (j, c) in s[i..].char_indices() {
                 state = self.dfa.transition(c, state);
-                if self.dfa.is_accept(state) {
-                    is_match = true;
-                    let mut chars = s[i+j..].char_indices();
-                    chars.next();
-                    end = if let Some((e,_)) = chars.next() {
-                        i+j+e
-                    }
-                    else {
-                        s.len()
-                    }
+                let next = i + j + c.len_utf8();
+                if self.dfa.is_accept(state, s[next..].chars().next()) {
+                    end = Some(next);
                 }
                 else if self.dfa.is_dead(state) {
                     break;
                 }
             }
-            if is_match {
+
+            if let Some(end) = end {
                 return Some(Match {
                     start: i,
-                    end: end,
+                    end,
                     matched_str: &s[i..end]
                 });
             }
         }
-
         None
     }
 
-    pub fn find_at<'a>(&self, s: &'a str, start: usize) -> Option<Match<'a>> {
-        if self.dfa.is_accept(self.dfa.get_start()) {
-            return Some(Match {
-                start: start,
-                end: start,
-                matched_str: &s[start..start]
-            })
-        }
+    // every char boundary of s from start, including the end of s
+    fn positions(s: &str, start: usize) -> impl Iterator<Item = usize> + '_ {
+        s[start..].char_indices().map(move |(i, _)| start + i).chain(std::iter::once(s.len()))
+    }
 
-        for (i, _) in s[start..].char_indices() {
-            let mut state = self.dfa.get_start();
-            let mut is_match = false;
-            let mut end  = i;
-            for (j, c) in s[i+start..].char_indices() {
-                state = self.dfa.transition(c, state);
-                if self.dfa.is_accept(state) {
-                    is_match = true;
-                    let mut chars = s[start+i+j..].char_indices();
-                    chars.next();
-                    end = if let Some((e,_)) = chars.next() {
-                        i+j+e
-                    }
-                    else {
-                        s.len() - start
-                    }
-                }
-                else if self.dfa.is_dead(state) {
-                    break;
-                }
-            }
-            if is_match {
-                return Some(Match {
-                    start: start + i,
-                    end: start + end,
-                    matched_str: &s[start + i..start + end]
-                });
-            }
-        }
-        None
-    } 
+    /**
+     * @brief returns the capture groups of the leftmost-longest match; the DFA finds the match
+     *        and the NFA then tracks the groups within it only
+     */
+    pub fn captures<'a>(&self, s: &'a str) -> Option<Captures<'a>> {
+        let mat = self.find(s)?;
+        let slots = self.nfa.captures(s, mat.start(), mat.end(), 2 * self.captures_len())?;
+
+        Some(Captures {
+            s,
+            group_names: Arc::clone(&self.group_names),
+            slots
+        })
+    }
+
+    /**
+     * @return: the number of capture groups including the whole match as group 0
+     */
+    pub fn captures_len(&self) -> usize {
+        self.group_names.len() + 1
+    }
 
-    pub fn find_all<'a>(&self, s: &'a str) -> Matches<'a> {
-        let mut matches = VecDeque::<Match>::new();
-        let mut i = 0;
+    /**
+     * @return: the names of the capture groups in order, starting with None for the whole match
+     */
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        std::iter::once(None).chain(self.group_names.iter().map(|name| name.as_deref()))
+    }
 
-        while let Some(mat) = self.find_at(s, i) {
-            i = mat.end();
-            matches.push_back(mat);
+    /**
+     * @brief iterates over the successive non-overlapping matches in s, searching for each one only when it is asked for;
+     *        the search goes on one char after an empty match, and an empty match right at the end of
+     *        the previous match is skipped, e.g. `a*` finds ``, `aa` and `` in `baac`
+     */
+    pub fn find_iter<'r, 'a>(&'r self, s: &'a str) -> Matches<'r, 'a, T> {
+        Matches {
+            regex: self,
+            s,
+            pos: Some(0),
+            last_end: None
         }
+    }
 
-        Matches::new(matches)
+    /**
+     * @brief the same as find_iter
+     */
+    pub fn find_all<'r, 'a>(&'r self, s: &'a str) -> Matches<'r, 'a, T> {
+        self.find_iter(s)
     }
 }
 
@@ -180,24 +315,98 @@ impl<'a> Match<'a> {
     }
 }
 
-#[derive(Clone)]
-pub struct Matches<'a> {
-    matches: VecDeque<Match<'a>>
+impl<'a> Captures<'a> {
+    /**
+     * @return: the match of group i, or None if the group did not take part in the match
+     */
+    pub fn get(&self, i: usize) -> Option<Match<'a>> {
+        match (self.slots.get(2 * i)?, self.slots.get(2 * i + 1)?) {
+            (&Some(start), &Some(end)) => Some(Match {
+                start,
+                end,
+                matched_str: &self.s[start..end]
+            }),
+            _ => None
+        }
+    }
+
+    /**
+     * @return: the match of the group named name, or None if it did not take part in the match
+     */
+    pub fn name(&self, name: &str) -> Option<Match<'a>> {
+        let i = self.group_names.iter().position(|n| n.as_deref() == Some(name))?;
+        self.get(i + 1)
+    }
+
+    /**
+     * @return: the number of capture groups including the whole match as group 0
+     */
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
 }
 
+/**
+ * the matches of a regex in a string, found one by one as the iterator advances
+ */
+pub struct Matches<'r, 'a, T: DFAExt> {
+    regex: &'r TinyRegexInner<T>,
+    s: &'a str,
+    // the byte offset to search for the next match from, None once no match is left
+    pos: Option<usize>,
+    // the end of the previous match
+    last_end: Option<usize>
+}
 
-impl<'a> Iterator for Matches<'a> {
+impl<'r, 'a, T: DFAExt> Iterator for Matches<'r, 'a, T> {
     type Item = Match<'a>;
 
     fn next(&mut self) -> Option<Match<'a>> {
-        self.matches.pop_front()
+        loop {
+            let mat = match self.regex.find_at(self.s, self.pos?) {
+                Some(mat) => mat,
+                None => {
+                    self.pos = None;
+                    return None;
+                }
+            };
+
+            // every match moves the position forward, an empty one by a char
+            self.pos = if mat.is_empty() {
+                self.s[mat.end()..].chars().next().map(|c| mat.end() + c.len_utf8())
+            }
+            else {
+                Some(mat.end())
+            };
+
+            if !(mat.is_empty() && self.last_end == Some(mat.end())) {
+                self.last_end = Some(mat.end());
+                return Some(mat);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // the matches left start at distinct positions from pos, including the end of s
+        (0, self.pos.map(|pos| self.s.len() - pos + 1).or(Some(0)))
     }
 }
 
-impl<'a> Matches<'a> {
-    fn new(matches: VecDeque<Match<'a>>) -> Matches<'a> {
+// once no match is left, the iterator does no more search
+impl<'r, 'a, T: DFAExt> std::iter::FusedIterator for Matches<'r, 'a, T> {}
+
+// cloned by hand, since the regex is shared rather than cloned
+impl<'r, 'a, T: DFAExt> Clone for Matches<'r, 'a, T> {
+    fn clone(&self) -> Matches<'r, 'a, T> {
         Matches {
-            matches
+            regex: self.regex,
+            s: self.s,
+            pos: self.pos,
+            last_end: self.last_end
         }
     }
 }
@@ -207,7 +416,7 @@ impl<'a> Matches<'a> {
 #[cfg(test)]
 #[cfg(not(feature = "on_the_fly"))]
 mod tests {
-    use crate::TinyRegex;
+    use crate::{TinyRegex, Error};
 
     #[test]
     fn test_find() {
@@ -276,96 +485,1861 @@ mod tests {
     }
 
     #[test]
-    fn test_greedy() {
-        let re = TinyRegex::new("a .* that is cute").unwrap();
-        let s = "I have a 🦀 that is cute. You have a 🐍 that is cute.";
+    fn test_plus_and_question() {
+        let re = TinyRegex::new("[0-9]+(:[0-9]+)?").unwrap();
+        let s = "meet at 10:30 or 9:05, room 42";
 
         let mut matches = re.find_all(s);
-        assert_eq!(matches.next().unwrap().as_str(), "a 🦀 that is cute. You have a 🐍 that is cute");
+        assert_eq!(matches.next().unwrap().as_str(), "10:30");
+        assert_eq!(matches.next().unwrap().as_str(), "9:05");
+        assert_eq!(matches.next().unwrap().as_str(), "42");
         assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new("colou?r").unwrap();
+        assert!(re.is_match("color"));
+        assert!(re.is_match("colour"));
+        assert!(!re.is_match("colouur"));
     }
 
     #[test]
-    fn test_escape_sequence() {
-        let re = TinyRegex::new(r".*\n").unwrap();
-        let s = "Hello\nWorld\n";
+    fn test_stacked_quantifiers() {
+        let re = TinyRegex::new("x(ab)+*y").unwrap();
+        assert!(re.is_match("xy"));
+        assert!(re.is_match("xababy"));
+        assert!(!re.is_match("xay"));
+
+        let re = TinyRegex::new("xa?+y").unwrap();
+        assert!(re.is_match("xy"));
+        assert!(re.is_match("xaaay"));
+
+        assert!(TinyRegex::new("+a").is_err());
+        assert!(TinyRegex::new("a|?").is_err());
+        assert!(TinyRegex::new("(*)").is_err());
+
+        // a `?` after a quantifier would make it lazy, and is rejected at the `?`
+        for (pattern, span) in [("a+?", 2..3), ("a*?", 2..3), ("a??", 2..3), ("a{2}?", 4..5), (r"\d+?", 3..4), ("x(ab)*?y", 6..7)] {
+            let err = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(err, Error::UnexpectedToken(_)), "{}", pattern);
+            assert_eq!(err.span(), span, "{}", pattern);
+        }
+        assert!(TinyRegex::new("a?+").is_ok());
+        assert!(TinyRegex::new("(a+)?").is_ok());
+    }
+
+    #[test]
+    fn test_counted_repetition() {
+        let re = TinyRegex::new("[0-9]{3} [0-9]{4}").unwrap();
+        assert_eq!(re.find("zip: 100 0001").unwrap().as_str(), "100 0001");
+        assert!(!re.is_match("zip: 10 0001"));
+
+        let re = TinyRegex::new("x(ab){2,3}y").unwrap();
+        assert!(!re.is_match("xaby"));
+        assert!(re.is_match("xababy"));
+        assert!(re.is_match("xabababy"));
+        assert!(!re.is_match("xababababy"));
+
+        let re = TinyRegex::new("xa{2,}y").unwrap();
+        assert!(!re.is_match("xay"));
+        assert!(re.is_match("xaaaaay"));
+
+        let re = TinyRegex::new("a{,2}").unwrap();
+        assert_eq!(re.find("a{,2}").unwrap().as_str(), "a{,2}");
+
+        assert!(TinyRegex::new("a{3,2}").is_err());
+        assert!(TinyRegex::new("{2}").is_err());
+    }
+
+    #[test]
+    fn test_size_limit() {
+        assert!(TinyRegex::new("a{1000}{1000}").is_err());
+        assert!(TinyRegex::builder("a{100}").size_limit(100).build().is_err());
+        assert!(TinyRegex::builder("a{10}").size_limit(100).build().is_ok());
+
+        // a counted repetition is as large as the copies spelled out
+        let literal = "a".repeat(20);
+        for pattern in ["a{20}", &literal] {
+            assert!(TinyRegex::builder(pattern).size_limit(42).build().is_ok(), "{}", pattern);
+            assert!(matches!(TinyRegex::builder(pattern).size_limit(41).build().err(), Some(Error::TooLarge(_))), "{}", pattern);
+        }
+        assert!(TinyRegex::builder("(ab){2,3}").size_limit(28).build().is_ok());
+        assert!(TinyRegex::builder("(ab){2,3}").size_limit(27).build().is_err());
+
+        // a small NFA can still need exponentially many DFA states, one for each of the last 21 chars read here
+        let e = TinyRegex::builder("[ab]*a[ab]{20}").dfa_size_limit(1_000).build().err().unwrap();
+        assert!(matches!(e, Error::TooLarge(_)));
+        assert_eq!(e.span(), 0..14);
+        let re = TinyRegex::builder("[ab]*a[ab]{5}").dfa_size_limit(1_000).build().unwrap();
+        assert_eq!(re.find("xbabbbbby").unwrap().range(), 1..8);
+    }
+
+    #[test]
+    fn test_anchors() {
+        let re = TinyRegex::new("^[0-9]+").unwrap();
+        assert_eq!(re.find("123abc456").unwrap().as_str(), "123");
+        assert!(!re.is_match("abc456"));
+        assert_eq!(re.find_at("123abc456", 6), None);
+
+        let re = TinyRegex::new("[0-9]+$").unwrap();
+        assert_eq!(re.find("123abc456").unwrap().range(), 6..9);
+        assert!(!re.is_match("123abc"));
+
+        let re = TinyRegex::new(r"\Aうにょ*\z").unwrap();
+        assert!(re.is_match("うにょょょ"));
+        assert!(!re.is_match("うにょ~"));
+        assert!(!re.is_match(" うにょ"));
+
+        let re = TinyRegex::new("(^|,)ab(,|$)").unwrap();
+        assert_eq!(re.find("ab,").unwrap().range(), 0..3);
+        assert_eq!(re.find("xab,ab").unwrap().range(), 3..6);
+        assert!(!re.is_match("xabx"));
+
+        let re = TinyRegex::new("$").unwrap();
+        assert_eq!(re.find("abc").unwrap().range(), 3..3);
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        let re = TinyRegex::new(r"\bcat\b").unwrap();
+        assert_eq!(re.find("concat cat catalog").unwrap().range(), 7..10);
+        assert!(!re.is_match("concat catalog"));
+
+        let re = TinyRegex::new(r"\Bcat").unwrap();
+        assert_eq!(re.find("cat concat").unwrap().range(), 7..10);
+
+        let re = TinyRegex::new(r"\b[^・]+\b").unwrap();
+        let s = "エドワード・ノートン\n";
 
         let mut matches = re.find_all(s);
-        assert_eq!(matches.next().unwrap().as_str(), "Hello\n");
-        assert_eq!(matches.next().unwrap().as_str(), "World\n");
+        assert_eq!(matches.next().unwrap().as_str(), "エドワード");
+        assert_eq!(matches.next().unwrap().as_str(), "ノートン");
         assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"\Bトン\b").unwrap();
+        assert_eq!(re.find("トン ノートン").unwrap().as_str(), "トン");
+        assert_eq!(re.find("トン ノートン").unwrap().start(), 13);
+
+        // without Unicode, the word chars are `[0-9A-Za-z_]` as for `\w`
+        let re = TinyRegex::builder(r"\bfe\b").unicode(false).build().unwrap();
+        assert_eq!(re.find("éfe fe").unwrap().range(), 2..4);
+        assert_eq!(TinyRegex::new(r"\bfe\b").unwrap().find("éfe fe").unwrap().range(), 5..7);
+        let re = TinyRegex::builder(r"\Bé").unicode(false).build().unwrap();
+        assert!(!re.is_match("café"));
+        assert!(TinyRegex::new(r"\Bé").unwrap().is_match("café"));
     }
-}
 
-#[cfg(test)]
-#[cfg(feature = "on_the_fly")]
-mod tests {
-    use crate::TinyRegex;
+    #[test]
+    fn test_escaped_meta_character() {
+        let re = TinyRegex::new(r"\(\*\)\.").unwrap();
+        assert_eq!(re.find("f(*).").unwrap().range(), 1..5);
+        assert!(TinyRegex::new(r"\q").is_err());
+    }
 
     #[test]
-    fn test_find() {
-        let re = TinyRegex::new("[a-zA-Z][a-zA-Z0-9]*").unwrap();
-        let s = "うにょ hello114514";
-        let mat = re.find(s).unwrap();
+    fn test_perl_classes() {
+        let re = TinyRegex::new(r"\d+").unwrap();
+        let mut matches = re.find_all("abc١٢٣ 456");
+        assert_eq!(matches.next().unwrap().as_str(), "١٢٣");
+        assert_eq!(matches.next().unwrap().as_str(), "456");
+        assert_eq!(matches.next(), None);
 
-        assert_eq!(10..21, mat.range());
-        assert_eq!("hello114514", &s[mat.range()]);
-        assert_eq!("hello114514", mat.as_str());
+        let re = TinyRegex::builder(r"\d+").unicode(false).build().unwrap();
+        let mut matches = re.find_all("abc١٢٣ 456");
+        assert_eq!(matches.next().unwrap().as_str(), "456");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"\w+").unwrap();
+        assert_eq!(re.find("・ノートン・").unwrap().as_str(), "ノートン");
+
+        let re = TinyRegex::new(r"\S+\s\D").unwrap();
+        assert_eq!(re.find("12 34\u{3000}ab").unwrap().as_str(), "34\u{3000}a");
     }
 
     #[test]
-    fn test_for_readme() {
-        let re = TinyRegex::new("a(b|c)*d").unwrap();
-        assert!(re.is_match("abbbcd"));
-        assert!(!re.is_match("abbbce"));
+    fn test_perl_classes_in_brackets() {
+        let re = TinyRegex::builder(r"[\w-]+").unicode(false).build().unwrap();
+        let mut matches = re.find_all("tiny-regex, tiny_grep");
+        assert_eq!(matches.next().unwrap().as_str(), "tiny-regex");
+        assert_eq!(matches.next().unwrap().as_str(), "tiny_grep");
+        assert_eq!(matches.next(), None);
 
-        let mat = re.find("wxyzabbbcdeffe").unwrap();
-        assert_eq!(mat.start(), 4);
-        assert_eq!(mat.end(), 10);
-        assert_eq!(mat.as_str(), "abbbcd");
-        assert_eq!(mat.range(), 4..10);
+        let re = TinyRegex::builder(r"[^\W_]+").unicode(false).build().unwrap();
+        let mut matches = re.find_all("snake_case");
+        assert_eq!(matches.next().unwrap().as_str(), "snake");
+        assert_eq!(matches.next().unwrap().as_str(), "case");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"[^\s,]+").unwrap();
+        let mut matches = re.find_all("a,b c");
+        assert_eq!(matches.next().unwrap().as_str(), "a");
+        assert_eq!(matches.next().unwrap().as_str(), "b");
+        assert_eq!(matches.next().unwrap().as_str(), "c");
+        assert_eq!(matches.next(), None);
     }
 
     #[test]
-    fn test_find_all() {
-        let re = TinyRegex::new("[a-zA-Z][a-zA-Z]*").unwrap();
-        let s = "my name is Unyo";
+    fn test_posix_classes() {
+        let re = TinyRegex::new("[[:xdigit:]]+").unwrap();
+        let mut matches = re.find_all("0xDEADbeef");
+        assert_eq!(matches.next().unwrap().as_str(), "0");
+        assert_eq!(matches.next().unwrap().as_str(), "DEADbeef");
+        assert_eq!(matches.next(), None);
 
-        let mut matches = re.find_all(s);
-        assert_eq!(matches.next().unwrap().as_str(), "my");
-        assert_eq!(matches.next().unwrap().as_str(), "name");
-        assert_eq!(matches.next().unwrap().as_str(), "is");
-        assert_eq!(matches.next().unwrap().as_str(), "Unyo");
+        let re = TinyRegex::new("[[:upper:]][[:lower:][:digit:]]*").unwrap();
+        assert_eq!(re.find("the Ωmega42 release").unwrap().as_str(), "Ωmega42");
+
+        let re = TinyRegex::builder("[[:alpha:]_][[:alnum:]_]*").unicode(false).build().unwrap();
+        assert_eq!(re.find("3 + _x1 * é").unwrap().as_str(), "_x1");
+
+        let re = TinyRegex::new("[^[:space:][:punct:]]+").unwrap();
+        let mut matches = re.find_all("Hello, 世界!");
+        assert_eq!(matches.next().unwrap().as_str(), "Hello");
+        assert_eq!(matches.next().unwrap().as_str(), "世界");
         assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new("[^[:^digit:]5]+").unwrap();
+        assert_eq!(re.find("a12345678").unwrap().as_str(), "1234");
+
+        assert!(TinyRegex::new("[[:vowel:]]").is_err());
     }
 
     #[test]
-    fn test_negchar() {
-        let re = TinyRegex::new("[^・ー]").unwrap();
-        let s = "エドワード・ノートン\n";
+    fn test_unicode_classes() {
+        let re = TinyRegex::new(r"\p{Greek}+").unwrap();
+        assert_eq!(re.find("alpha is αλφα").unwrap().as_str(), "αλφα");
 
-        let mut matches = re.find_all(s);
-        assert_eq!(matches.next().unwrap().as_str(), "エ");
-        assert_eq!(matches.next().unwrap().as_str(), "ド");
-        assert_eq!(matches.next().unwrap().as_str(), "ワ");
-        assert_eq!(matches.next().unwrap().as_str(), "ド");
-        assert_eq!(matches.next().unwrap().as_str(), "ノ");
-        assert_eq!(matches.next().unwrap().as_str(), "ト");
-        assert_eq!(matches.next().unwrap().as_str(), "ン");
-        assert_eq!(matches.next().unwrap().as_str(), "\n");
+        let re = TinyRegex::new(r"\p{Script=Hiragana}+").unwrap();
+        let mut matches = re.find_all("ひらがなとカタカナ");
+        assert_eq!(matches.next().unwrap().as_str(), "ひらがなと");
         assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"\p{Lu}\p{Ll}+").unwrap();
+        assert_eq!(re.find("ΑΒ Ωmega").unwrap().as_str(), "Ωmega");
+
+        let re = TinyRegex::new(r"[\p{sc=Katakana}ー・]+").unwrap();
+        assert_eq!(re.find("俳優のエドワード・ノートン").unwrap().as_str(), "エドワード・ノートン");
+
+        let re = TinyRegex::new(r"\P{Han}+").unwrap();
+        assert_eq!(re.find("俳優のエドワード").unwrap().as_str(), "のエドワード");
+
+        let re = TinyRegex::new(r"[^\p{^Nd}5]+").unwrap();
+        assert_eq!(re.find("x١٢٣45").unwrap().as_str(), "١٢٣4");
+
+        assert!(TinyRegex::new(r"\pN").unwrap().is_match("Ⅻ"));
+        assert!(TinyRegex::new(r"\p{Klingon}").is_err());
+        assert!(TinyRegex::new(r"\p{Greek").is_err());
     }
 
     #[test]
-    fn test_any_char() {
-        let re = TinyRegex::new(" ... ").unwrap();
-        let s = "Das Alter beginnt in dem Augenblick, wo man nicht mehr ohne die Vergangenheit leben kann.";
+    fn test_numeric_escapes() {
+        let re = TinyRegex::new(r"\x41\x{3b1}\u{1F600}\u00E9").unwrap();
+        assert_eq!(re.find("xAα😀é").unwrap().as_str(), "Aα😀é");
 
-        let mut matches = re.find_all(s);
-        assert_eq!(matches.next().unwrap().as_str(), " dem ");
-        assert_eq!(matches.next().unwrap().as_str(), " man ");
-        assert_eq!(matches.next().unwrap().as_str(), " die ");
+        let re = TinyRegex::new(r"[\x30-\x39]+\o{41}").unwrap();
+        assert_eq!(re.find("abc123!").unwrap().as_str(), "123!");
+
+        let re = TinyRegex::new(r"\011\0\cA\cj\c?").unwrap();
+        assert!(re.is_match("\t\0\x01\n\x7F"));
+
+        assert!(TinyRegex::new(r"\x4").is_err());
+        assert!(TinyRegex::new(r"\x{}").is_err());
+        assert!(TinyRegex::new(r"\x{41").is_err());
+        assert!(TinyRegex::new(r"\x{110000}").is_err());
+        assert!(TinyRegex::new(r"\x{FFFFFFFFFF}").is_err());
+        assert!(TinyRegex::new(r"\u{D800}").is_err());
+        assert!(TinyRegex::new(r"\o{8}").is_err());
+        assert!(TinyRegex::new(r"\c1").is_err());
+    }
+
+    #[test]
+    fn test_captures() {
+        let re = TinyRegex::new(r"(\d{4}),(\d{2}),(\d{2}) (\w+)").unwrap();
+        assert_eq!(re.captures_len(), 5);
+        let caps = re.captures("date: 2024,01,31 tuesday").unwrap();
+        assert_eq!(caps.len(), 5);
+        assert_eq!(caps.get(0).unwrap().as_str(), "2024,01,31 tuesday");
+        assert_eq!(caps.get(1).unwrap().as_str(), "2024");
+        assert_eq!(caps.get(2).unwrap().range(), 11..13);
+        assert_eq!(caps.get(3).unwrap().as_str(), "31");
+        assert_eq!(caps.get(4).unwrap().as_str(), "tuesday");
+        assert_eq!(caps.get(5), None);
+        assert!(re.captures("no date").is_none());
+
+        // the groups follow the leftmost-longest match the DFA finds
+        let re = TinyRegex::new(r"(a|ab)(c|bcd)(d*)").unwrap();
+        let caps = re.captures("abcd").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "abcd");
+        assert_eq!(caps.get(1).unwrap().as_str(), "a");
+        assert_eq!(caps.get(2).unwrap().as_str(), "bcd");
+        assert_eq!(caps.get(3).unwrap().as_str(), "");
+
+        // greedy groups take as much as the match allows, and the last iteration wins
+        let re = TinyRegex::new(r"(\w+)(\d+)").unwrap();
+        let caps = re.captures("abc123").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "abc12");
+        assert_eq!(caps.get(2).unwrap().as_str(), "3");
+
+        let re = TinyRegex::new(r"((ア|イ)ー)+").unwrap();
+        let caps = re.captures("アーイーウ").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "アーイー");
+        assert_eq!(caps.get(1).unwrap().as_str(), "イー");
+        assert_eq!(caps.get(2).unwrap().as_str(), "イ");
+
+        // a group not taking part in the match
+        let re = TinyRegex::new(r"(x)?\b(y)").unwrap();
+        let caps = re.captures("z y").unwrap();
+        assert_eq!(caps.get(1), None);
+        assert_eq!(caps.get(2).unwrap().start(), 2);
+    }
+
+    #[test]
+    fn test_named_groups() {
+        let re = TinyRegex::new(r"(?P<key>\w+)=(?:(?<quoted>'[^']*')|(?P<bare>\w*))").unwrap();
+        assert_eq!(re.captures_len(), 4);
+        assert_eq!(re.capture_names().collect::<Vec<_>>(), vec![None, Some("key"), Some("quoted"), Some("bare")]);
+
+        let caps = re.captures("set name='tiny regex'").unwrap();
+        assert_eq!(caps.name("key").unwrap().as_str(), "name");
+        assert_eq!(caps.name("quoted").unwrap().as_str(), "'tiny regex'");
+        assert_eq!(caps.get(2).unwrap().as_str(), "'tiny regex'");
+        assert_eq!(caps.name("bare"), None);
+        assert_eq!(caps.name("value"), None);
+
+        let caps = re.captures("set size=42").unwrap();
+        assert_eq!(caps.name("bare").unwrap().as_str(), "42");
+
+        // non-capturing groups do not shift the numbering
+        let re = TinyRegex::new(r"(?:ア|イ)+(ウ)").unwrap();
+        assert_eq!(re.capture_names().collect::<Vec<_>>(), vec![None, None]);
+        assert_eq!(re.captures("アイウ").unwrap().get(1).unwrap().as_str(), "ウ");
+
+        assert!(TinyRegex::new(r"(?P<a>x)(?P<a>y)").is_err());
+        assert!(TinyRegex::new(r"(?P<>x)").is_err());
+        assert!(TinyRegex::new(r"(?P<1a>x)").is_err());
+        assert!(TinyRegex::new(r"(?P<a x)").is_err());
+        assert!(TinyRegex::new(r"(?=x)").is_err());
+    }
+
+    #[test]
+    fn test_inline_flags() {
+        let re = TinyRegex::new(r"(?i)tiny regex").unwrap();
+        assert_eq!(re.find("This is TiNy ReGeX").unwrap().as_str(), "TiNy ReGeX");
+
+        let re = TinyRegex::new(r"(?i)[a-c]+|[α-γ]+").unwrap();
+        assert_eq!(re.find("xyzaBC").unwrap().as_str(), "aBC");
+        assert_eq!(re.find("ΑΒΓ").unwrap().as_str(), "ΑΒΓ");
+
+        // the flags are scoped by the enclosing group
+        let re = TinyRegex::new(r"a(?i:b)c((?i)d)e").unwrap();
+        assert!(re.is_match("aBcDe"));
+        assert!(!re.is_match("aBCDe"));
+        assert!(!re.is_match("aBcDE"));
+
+        let re = TinyRegex::new(r"(?i)a(?-i)b").unwrap();
+        assert!(re.is_match("Ab"));
+        assert!(!re.is_match("AB"));
+
+        // multi-line anchors match at line feeds
+        let re = TinyRegex::new(r"(?m)^\w+$").unwrap();
+        let mut matches = re.find_all("first\nsecond line\nthird");
+        assert_eq!(matches.next().unwrap().as_str(), "first");
+        assert_eq!(matches.next().unwrap().as_str(), "third");
         assert_eq!(matches.next(), None);
+        assert_eq!(TinyRegex::new(r"^\w+$").unwrap().find("first\nthird"), None);
+        assert!(TinyRegex::new(r"(?m)\A\w+$").unwrap().find("a\nb").is_some_and(|mat| mat.as_str() == "a"));
+
+        // dot-matches-newline can be cleared
+        let re = TinyRegex::new(r"(?-s)a.+").unwrap();
+        assert_eq!(re.find("xab\nc").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::new(r"(?ims)^a.b$").unwrap();
+        assert!(re.is_match("x\nA\nB"));
+
+        assert!(TinyRegex::new(r"(?q)a").is_err());
+        assert!(TinyRegex::new(r"(?i-m-s)a").is_err());
+        assert!(TinyRegex::new(r"(?)a").is_err());
+        assert!(TinyRegex::new(r"(?i").is_err());
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let re = TinyRegex::new(r"(?i)straße").unwrap();
+        assert!(re.is_match("STRAẞE"));
+        assert!(!re.is_match("STRASSE"));
+
+        let re = TinyRegex::new(r"(?i)σοφία").unwrap();
+        assert!(re.is_match("ΣΟΦΊΑ"));
+        assert!(TinyRegex::new(r"(?i)λόγος").unwrap().is_match("ΛΌΓΟΣ"));
+
+        // the Kelvin sign and the long s fold to ASCII letters
+        let re = TinyRegex::new(r"(?i)^[a-z]+$").unwrap();
+        assert!(re.is_match("Kelvin"));
+        assert!(re.is_match("\u{212A}elvin"));
+        assert!(re.is_match("\u{17F}"));
+
+        // the chars excluded by a negated class are folded before the negation
+        let re = TinyRegex::new(r"(?i)[^a-z]").unwrap();
+        assert!(!re.is_match("A"));
+        assert!(!re.is_match("\u{212A}"));
+        assert!(!re.is_match("\u{17F}"));
+        assert!(re.is_match("1"));
+
+        let re = TinyRegex::new(r"(?i)[^\Wk]").unwrap();
+        assert!(re.is_match("j"));
+        assert!(!re.is_match("K"));
+        assert!(!re.is_match("\u{212A}"));
+
+        let re = TinyRegex::new(r"(?i)[^\p{Lu}]").unwrap();
+        assert!(!re.is_match("a"));
+        assert!(re.is_match("1"));
+
+        let re = TinyRegex::new(r"(?i)\p{Lu}").unwrap();
+        assert!(re.is_match("a"));
+        assert!(!re.is_match("1"));
+    }
+
+    #[test]
+    fn test_dot_and_new_lines() {
+        let re = TinyRegex::new(r"a.*").unwrap();
+        assert_eq!(re.find("xab\ncd").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::builder(r"a.*").dot_matches_new_line(true).build().unwrap();
+        assert_eq!(re.find("xab\ncd").unwrap().as_str(), "ab\ncd");
+        assert_eq!(TinyRegex::new(r"(?s)a.*").unwrap().find("xab\ncd").unwrap().as_str(), "ab\ncd");
+        assert_eq!(TinyRegex::new(r"(?s:a.)+").unwrap().find("a\na\n").unwrap().as_str(), "a\na\n");
+
+        // `.` still matches `\r` unless in CRLF mode
+        assert_eq!(TinyRegex::new(r"a.*").unwrap().find("ab\r\n").unwrap().as_str(), "ab\r");
+        let re = TinyRegex::builder(r"a.*").crlf(true).build().unwrap();
+        assert_eq!(re.find("ab\r\n").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::new(r"(?mR)^\w+$").unwrap();
+        let mut matches = re.find_all("first\r\nsecond\rthird\n");
+        assert_eq!(matches.next().unwrap().as_str(), "first");
+        assert_eq!(matches.next().unwrap().as_str(), "second");
+        assert_eq!(matches.next().unwrap().as_str(), "third");
+
+        // never between `\r` and `\n`
+        let re = TinyRegex::new(r"(?mR)^$").unwrap();
+        assert!(!re.is_match("a\r\nb"));
+        assert!(re.is_match("a\r\n\r\nb"));
+        assert!(!TinyRegex::new(r"(?m)^$").unwrap().is_match("a\r\nb"));
+        assert!(TinyRegex::new(r"(?m)\r$").unwrap().is_match("a\r\nb"));
+    }
+
+    #[test]
+    fn test_verbose() {
+        let re = TinyRegex::new(r"(?x)
+            (?P<year> \d{4} ) ,   # the year
+            (?P<month> \d{2} )    # the month
+            \ \#                  # escaped whitespace and number sign
+        ").unwrap();
+        let caps = re.captures("on 2024,01 #").unwrap();
+        assert_eq!(caps.name("year").unwrap().as_str(), "2024");
+        assert_eq!(caps.name("month").unwrap().as_str(), "01");
+        assert_eq!(caps.get(0).unwrap().as_str(), "2024,01 #");
+
+        // whitespace inside brackets is kept
+        let re = TinyRegex::new(r"(?x) [ #]+ ").unwrap();
+        assert_eq!(re.find("a # b").unwrap().as_str(), " # ");
+
+        // verbose mode is scoped like the other flags
+        let re = TinyRegex::new(r"(?x: a b ) c d").unwrap();
+        assert!(re.is_match("ab c d"));
+        assert!(!re.is_match("abcd"));
+        let re = TinyRegex::new(r"a b(?x) c d").unwrap();
+        assert!(re.is_match("a bcd"));
+        let re = TinyRegex::new(r"((?x) a b ) c").unwrap();
+        assert!(re.is_match("ab c"));
+
+        // whitespace around the counts of a repetition is ignored too, but not outside verbose mode
+        let re = TinyRegex::new(r"(?x)a{2, 3}").unwrap();
+        assert_eq!(re.find("baaaab").unwrap().as_str(), "aaa");
+        assert!(!re.is_match("a{2,3}"));
+        let re = TinyRegex::new(r"(?x)a{ 2 }").unwrap();
+        assert_eq!(re.find("baaab").unwrap().as_str(), "aa");
+        let re = TinyRegex::new(r"a{2, 3}").unwrap();
+        assert!(re.is_match("a{2, 3}"));
+        assert!(!re.is_match("aa"));
+    }
+
+    #[test]
+    fn test_quote() {
+        let re = TinyRegex::new(r"\Q1+1=(2)\E!*").unwrap();
+        assert_eq!(re.find("so 1+1=(2)!!").unwrap().as_str(), "1+1=(2)!!");
+        assert!(!re.is_match("11=2"));
+
+        let re = TinyRegex::new(r"a\Q.*").unwrap();
+        assert!(re.is_match("a.*"));
+        assert!(!re.is_match("abc"));
+
+        let re = TinyRegex::new(r"(?x) \Q# not a comment\E").unwrap();
+        assert!(re.is_match("# not a comment"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(crate::escape("a.b*c"), r"a\.b\*c");
+        assert_eq!(crate::escape("[ア-ン]"), r"\[ア\-ン\]");
+
+        // a pseudo-random generator, so that the strings are the same every run
+        let pool = "ab .+*?()|[]{}^$#&-~\\\n\t/:エ😀";
+        let pool = pool.chars().collect::<Vec<char>>();
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..200 {
+            let len = next() % 12;
+            let s = (0..len).map(|_| pool[(next() % pool.len() as u64) as usize]).collect::<String>();
+            let re = TinyRegex::new(&crate::escape(&s)).unwrap();
+            assert_eq!(re.find(&s).unwrap().as_str(), s);
+        }
+    }
+
+    #[test]
+    fn test_class_lexing() {
+        let re = TinyRegex::new(r"[.*+?()|{}$]+").unwrap();
+        assert_eq!(re.find("ab(a+b)*{c}$").unwrap().as_str(), "(");
+        assert_eq!(re.find("x.*+?()|{}$y").unwrap().as_str(), ".*+?()|{}$");
+
+        let re = TinyRegex::new(r"[]a]+").unwrap();
+        assert_eq!(re.find("b]a]c").unwrap().as_str(), "]a]");
+        let re = TinyRegex::new(r"[^]a]+").unwrap();
+        assert_eq!(re.find("]a]bc]").unwrap().as_str(), "bc");
+
+        let re = TinyRegex::new(r"[a-]+").unwrap();
+        assert_eq!(re.find("b-a-c").unwrap().as_str(), "-a-");
+        let re = TinyRegex::new(r"[-a]+").unwrap();
+        assert_eq!(re.find("b-a-c").unwrap().as_str(), "-a-");
+        let re = TinyRegex::new(r"[^-a]+").unwrap();
+        assert_eq!(re.find("-a-bc-").unwrap().as_str(), "bc");
+
+        // `^` is literal except as the first char
+        let re = TinyRegex::new(r"[a^]+").unwrap();
+        assert_eq!(re.find("b^a^c").unwrap().as_str(), "^a^");
+        let re = TinyRegex::new(r"[^^]+").unwrap();
+        assert_eq!(re.find("^^ab^").unwrap().as_str(), "ab");
+
+        // `(` and `#` inside brackets neither open a group nor start a comment
+        let re = TinyRegex::new(r"(?x) [(#] + ").unwrap();
+        assert_eq!(re.find("a(#b").unwrap().as_str(), "(#");
+
+        // `-` and `]` outside brackets are ordinary characters
+        let re = TinyRegex::new(r"\d+-\d+]").unwrap();
+        assert_eq!(re.find("[12-34]").unwrap().as_str(), "12-34]");
+
+        assert!(TinyRegex::new(r"[]").is_err());
+        assert!(TinyRegex::new(r"[^]").is_err());
+    }
+
+    #[test]
+    fn test_class_set_operations() {
+        let re = TinyRegex::new(r"[\p{L}--[aeiou]]+").unwrap();
+        assert_eq!(re.find("aeiXyzéo").unwrap().as_str(), "Xyzé");
+
+        let re = TinyRegex::new(r"[\p{Greek}&&\p{Ll}]+").unwrap();
+        assert_eq!(re.find("ΑΒγδΕ").unwrap().as_str(), "γδ");
+
+        // nested classes are unions, and `&&` and `--` bind looser and associate to the left
+        let re = TinyRegex::new(r"[a[bc]]+").unwrap();
+        assert_eq!(re.find("xcabd").unwrap().as_str(), "cab");
+        let re = TinyRegex::new(r"[a-z&&[^aeiou]--xyz]+").unwrap();
+        assert_eq!(re.find("xbcdefy").unwrap().as_str(), "bcd");
+        let re = TinyRegex::new(r"[^[a-z]--[aeiou]]+").unwrap();
+        assert_eq!(re.find("bcaeXd").unwrap().as_str(), "aeX");
+        let re = TinyRegex::new(r"[\w--\d]+").unwrap();
+        assert_eq!(re.find("12ab_3").unwrap().as_str(), "ab_");
+        let re = TinyRegex::new(r"[^\W&&\D]+").unwrap();
+        assert_eq!(re.find("ab12_-").unwrap().as_str(), "ab12_");
+
+        let re = TinyRegex::new(r"(?i)[a-z--[aeiou]]+").unwrap();
+        assert_eq!(re.find("AEBCdE").unwrap().as_str(), "BCd");
+
+        // a single `&` and a `-` at either end are literal
+        let re = TinyRegex::new(r"[a&-]+").unwrap();
+        assert_eq!(re.find("b&-a").unwrap().as_str(), "&-a");
+
+        assert!(TinyRegex::new(r"[a[bc]").is_err());
+
+        // the `]` right after `&&` or `--` closes the class, leaving the operator without an operand
+        for (pattern, span) in [(r"[a&&]", 2..4), (r"[a--]", 2..4), (r"[+&&]", 2..4), (r"x[[ab]--]y", 6..8)] {
+            let e = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(e, Error::UnexpectedToken(_)), "{}", pattern);
+            assert_eq!(e.span(), span, "{}", pattern);
+        }
+        let spans = crate::check(r"[a--]b)").iter().map(|e| e.span()).collect::<Vec<_>>();
+        assert_eq!(spans, [2..4, 6..7]);
+    }
+
+    #[test]
+    fn test_large_classes() {
+        let re = TinyRegex::new(r"[\u{4E00}-\u{9FFF}\u{20000}-\u{2A6DF}]+").unwrap();
+        assert_eq!(re.find("kanji: 漢字𠀋です").unwrap().as_str(), "漢字𠀋");
+
+        let re = TinyRegex::new(r"[\x{0}-\x{D7FF}\x{E000}-\x{10FFFF}]").unwrap();
+        assert_eq!(re.find("😀").unwrap().as_str(), "😀");
+        let re = TinyRegex::new(r"a[^\x{0}-\x{D7FF}\x{E000}-\x{10FFFF}]").unwrap();
+        assert!(re.find("a😀").is_none());
+
+        let re = TinyRegex::new(r"[^a]+").unwrap();
+        assert_eq!(re.find("aエ😀\u{10FFFF}a").unwrap().as_str(), "エ😀\u{10FFFF}");
+
+        let re = TinyRegex::new(r"(?i)\b[\p{L}--\p{Han}]+\b").unwrap();
+        assert_eq!(re.find("漢字 Ωmega").unwrap().as_str(), "Ωmega");
+    }
+
+    #[test]
+    fn test_empty() {
+        let re = TinyRegex::new("").unwrap();
+        assert_eq!(re.find("abc").unwrap().range(), 0..0);
+        assert!(re.is_match(""));
+        assert_eq!(re.find("\0").unwrap().range(), 0..0);
+
+        let re = TinyRegex::new("a|").unwrap();
+        assert_eq!(re.find("ba").unwrap().range(), 0..0);
+        assert_eq!(re.find("ab").unwrap().as_str(), "a");
+        let re = TinyRegex::new("|a").unwrap();
+        assert_eq!(re.find("ab").unwrap().as_str(), "a");
+
+        let re = TinyRegex::new("(|b)c").unwrap();
+        assert_eq!(re.find("abc").unwrap().as_str(), "bc");
+        assert_eq!(re.find("ac").unwrap().as_str(), "c");
+        let caps = re.captures("ac").unwrap();
+        assert_eq!(caps.get(1).unwrap().range(), 1..1);
+
+        let re = TinyRegex::new("a()*b").unwrap();
+        assert_eq!(re.find("xab").unwrap().as_str(), "ab");
+        let re = TinyRegex::new("a(?:)b").unwrap();
+        assert_eq!(re.find("xab").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::new("ab{0}c").unwrap();
+        assert_eq!(re.find("abcac").unwrap().as_str(), "ac");
+    }
+
+    #[test]
+    fn test_errors() {
+        let spans = [
+            ("a(b|c", 1..2),
+            ("ab)c", 2..3),
+            ("x[a-z", 1..2),
+            ("[a[b]", 0..1),
+            ("a{3,2}b", 1..6),
+            ("[a-\\d]", 1..5),
+            ("ab\\", 2..3),
+            ("a\\qb", 1..3),
+            ("\\x{110000}", 0..10),
+            ("(?x) \\p{Klingon}", 5..16),
+            ("[[:vowel:]]", 1..10),
+            ("(?P<1a>x)", 0..7),
+            ("(?P<a>x)(?P<a>y)", 8..14),
+            ("(?q)", 0..3),
+            ("a|*", 2..3),
+            ("a{100}", 1..6)
+        ];
+        for (pattern, span) in spans {
+            let e = TinyRegex::builder(pattern).size_limit(100).build().err().unwrap();
+            assert_eq!(e.span(), span, "{}", pattern);
+            assert_eq!(e.pattern(), pattern);
+        }
+
+        assert!(matches!(TinyRegex::new("a(b|c").err(), Some(Error::UnclosedGroup(_))));
+        assert!(matches!(TinyRegex::new("ab)c").err(), Some(Error::UnopenedGroup(_))));
+        assert!(matches!(TinyRegex::new("[a-z").err(), Some(Error::UnclosedClass(_))));
+        assert!(matches!(TinyRegex::new("a{3,2}").err(), Some(Error::BadRange(_))));
+        assert!(matches!(TinyRegex::new("ab\\").err(), Some(Error::DanglingEscape(_))));
+        assert!(matches!(TinyRegex::new("a\\qb").err(), Some(Error::BadEscape(_))));
+        assert!(matches!(TinyRegex::new("\\p{Klingon}").err(), Some(Error::UnknownClass(_))));
+        assert!(matches!(TinyRegex::new("(?q)").err(), Some(Error::BadGroup(_))));
+        assert!(matches!(TinyRegex::new("+a").err(), Some(Error::DanglingQuantifier(_))));
+        assert!(matches!(TinyRegex::builder("a{100}").size_limit(100).build().err(), Some(Error::TooLarge(_))));
+
+        let e = TinyRegex::new("ab\\qc").err().unwrap();
+        assert_eq!(e.to_string(), "unknown escape sequence \\q\nab\\qc\n  ^^");
+        let e = TinyRegex::new("(?x)\n\ta\\q  # comment").err().unwrap();
+        assert_eq!(e.to_string(), "unknown escape sequence \\q\n\ta\\q  # comment\n\t ^^");
+        let e = TinyRegex::new("(ab").err().unwrap();
+        assert_eq!(e.to_string(), "( is not closed by )\n(ab\n^");
+        let e: Box<dyn std::error::Error> = Box::new(TinyRegex::new("(?").err().unwrap());
+        assert!(e.to_string().starts_with("(? is not closed"));
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        let spans = [
+            ("ab[z-a]", 3..6),
+            ("[^c-a]", 2..5),
+            ("[\\x{10FFFF}-\\0]", 1..14),
+            ("[\\d-z]", 1..5),
+            ("[a-\\d]", 1..5),
+            ("[[:alpha:]-z]", 1..12),
+            ("[a-[bc]]", 1..4),
+            ("[a-c-e]", 4..5)
+        ];
+        for (pattern, span) in spans {
+            let e = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(e, Error::BadRange(_)), "{}", pattern);
+            assert_eq!(e.span(), span, "{}", pattern);
+        }
+
+        // a range may neither span the surrogates, which are not chars, nor end at a surrogate
+        for (pattern, span) in [(r"[\u{D7FF}-\u{E000}]", 1..18), (r"[a\0-\x{10FFFF}]", 2..15)] {
+            let e = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(e, Error::BadRange(_)), "{}", pattern);
+            assert_eq!(e.span(), span, "{}", pattern);
+        }
+        let re = TinyRegex::new(r"[\u{D000}-\u{D7FF}\u{E000}-\u{E0FF}]+").unwrap();
+        assert_eq!(re.find("a\u{D7FF}\u{E000}b").unwrap().as_str(), "\u{D7FF}\u{E000}");
+        let e = TinyRegex::new(r"[\u{D000}-\u{D800}]").err().unwrap();
+        assert!(matches!(e, Error::BadEscape(_)));
+        assert_eq!(e.span(), 10..18);
+
+        // `-` is literal at either end and after a class right before `]`
+        let re = TinyRegex::new(r"[\d-]+").unwrap();
+        assert_eq!(re.find("a1-2b").unwrap().as_str(), "1-2");
+        let re = TinyRegex::new(r"[a-a]+").unwrap();
+        assert_eq!(re.find("baab").unwrap().as_str(), "aa");
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(crate::check(r"(?P<y>\d{4})-(\d{2})|[[:alpha:]--[aeiou]]+").is_empty());
+
+        // parsing goes on from the next `)`, `|` or `]` after each error
+        let errors = crate::check(r"a\q(b|*c)d)[z-a\d]x{3,2}\p{Foo}+");
+        let spans = errors.iter().map(|e| e.span()).collect::<Vec<_>>();
+        assert_eq!(spans, [1..3, 6..7, 10..11, 12..15, 19..24, 24..31]);
+        assert!(matches!(errors[0], Error::BadEscape(_)));
+        assert!(matches!(errors[1], Error::DanglingQuantifier(_)));
+        assert!(matches!(errors[2], Error::UnopenedGroup(_)));
+        assert!(matches!(errors[3], Error::BadRange(_)));
+        assert!(matches!(errors[4], Error::BadRange(_)));
+        assert!(matches!(errors[5], Error::UnknownClass(_)));
+
+        // the error TinyRegex::new stops at is one of them
+        let pattern = r"(?P<n>a)(?P<n>b)[[:vowel:]&&b]|?(c";
+        let errors = crate::check(pattern);
+        assert_eq!(errors.len(), 4);
+        assert!(errors.contains(&TinyRegex::new(pattern).err().unwrap()));
+        assert!(matches!(errors[3], Error::UnclosedGroup(_)));
+
+        // a `-` that a malformed escape ending the pattern leaves right before EOF is an ordinary character
+        let spans = |pattern: &str| crate::check(pattern).iter().map(|e| e.span()).collect::<Vec<_>>();
+        assert_eq!(spans(r"[a-\"), [0..1, 3..4]);
+        assert_eq!(spans(r"x[a-\"), [1..2, 4..5]);
+        let errors = crate::check(r"[a-\Q");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], Error::UnclosedClass(_)));
+
+        // a malformed escape at either end of a range is reported alone, as it stands for no char
+        assert_eq!(spans(r"[a-\c"), [0..1, 3..5]);
+        assert_eq!(spans(r"[a-\p{L"), [0..1, 3..7]);
+        assert_eq!(spans(r"[a-\q]b[\q-a]"), [3..5, 8..10]);
+    }
+
+    #[test]
+    fn test_nest_limit() {
+        // long sequences and many alternatives are parsed and built without recursion
+        let literal = "ab".repeat(5_000);
+        let re = TinyRegex::new(&literal).unwrap();
+        assert_eq!(re.find(&format!("x{}y", literal)).unwrap().range(), 1..10_001);
+        let words = (0..1_000).map(|i| format!("w{}x", i)).collect::<Vec<_>>();
+        let re = TinyRegex::new(&words.join("|")).unwrap();
+        assert_eq!(re.find("a w999x b").unwrap().as_str(), "w999x");
+
+        // a long bounded repetition stays a flat sequence
+        let re = TinyRegex::new("a{2,5000}").unwrap();
+        assert_eq!(re.find("baaab").unwrap().as_str(), "aaa");
+
+        // deep nesting is an error rather than a stack overflow
+        let deep = format!("{}a{}", "(".repeat(5_000), ")".repeat(5_000));
+        let e = TinyRegex::new(&deep).err().unwrap();
+        assert!(matches!(e, Error::TooDeep(_)));
+        assert_eq!(e.span(), 250..251);
+        let e = TinyRegex::new(&format!("{}a{}", "[".repeat(300), "]".repeat(300))).err().unwrap();
+        assert!(matches!(e, Error::TooDeep(_)));
+        let e = TinyRegex::new(&format!("a{}", "{0,1}".repeat(300))).err().unwrap();
+        assert!(matches!(e, Error::TooDeep(_)));
+        assert_eq!(e.span(), 1251..1256);
+        assert_eq!(crate::check(&deep).len(), 1);
+
+        let nested = format!("{}a{}", "(".repeat(20), ")".repeat(20));
+        assert!(TinyRegex::new(&nested).is_ok());
+        assert!(TinyRegex::builder(&nested).nest_limit(20).build().is_ok());
+        assert!(matches!(TinyRegex::builder(&nested).nest_limit(19).build().err(), Some(Error::TooDeep(_))));
+        assert!(TinyRegex::builder("[[a]]").nest_limit(1).build().is_err());
+
+        // nested brackets are parsed without recursion too, so raising the limit cannot overflow the stack
+        let deep = format!("{}a{}", "[".repeat(100_000), "]".repeat(100_000));
+        let re = TinyRegex::builder(&deep).nest_limit(usize::MAX).build().unwrap();
+        assert_eq!(re.find("ba").unwrap().range(), 1..2);
+
+        // and deep groups build in time linear in their depth
+        let deep = format!("{}a{}", "(".repeat(50_000), ")".repeat(50_000));
+        let re = TinyRegex::builder(&deep).nest_limit(usize::MAX).build().unwrap();
+        assert_eq!(re.find("ba").unwrap().range(), 1..2);
+        assert!(TinyRegex::builder("a{2}{2}").nest_limit(1).build().is_err());
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let re = TinyRegex::new("[0-9]+").unwrap();
+        let numbers = re.find_iter("1, 22 and 333").map(|mat| mat.as_str()).collect::<Vec<_>>();
+        assert_eq!(numbers, ["1", "22", "333"]);
+
+        // the matches are found on demand, and a clone goes on from where it was made
+        let s = "a1 b22 c333";
+        let mut matches = re.find_iter(s);
+        assert_eq!(matches.next().unwrap().range(), 1..2);
+        let mut rest = matches.clone();
+        assert_eq!(matches.next().unwrap().as_str(), "22");
+        assert_eq!(rest.next().unwrap().as_str(), "22");
+
+        // once exhausted, the iterator stays so
+        fn fused<I: std::iter::FusedIterator>(iter: I) -> I {
+            iter
+        }
+        let mut matches = fused(re.find_iter(s));
+        assert_eq!(matches.by_ref().count(), 3);
+        assert!(matches.next().is_none());
+        assert!(matches.next().is_none());
+        assert_eq!(matches.size_hint(), (0, Some(0)));
+
+        // the bounds hold the number of matches left before every next()
+        for (pattern, s) in [("[0-9]+", "a1 b22 c333"), ("a*", "baaeé"), ("", "añb"), ("x", "abc")] {
+            let re = TinyRegex::new(pattern).unwrap();
+            let mut matches = re.find_iter(s);
+            loop {
+                let (lower, upper) = matches.size_hint();
+                let left = matches.clone().count();
+                assert!(lower <= left && upper.is_none_or(|upper| left <= upper), "{} over {:?}", pattern, s);
+                if matches.next().is_none() {
+                    break;
+                }
+            }
+        }
+
+        // one next() yields the first match alone and leaves the iterator right after it
+        let re = TinyRegex::new("b|a*c").unwrap();
+        let mut matches = re.find_iter("baacbac");
+        assert_eq!(matches.next().unwrap().range(), 0..1);
+        assert_eq!(matches.map(|mat| mat.range()).collect::<Vec<_>>(), [1..4, 4..5, 5..7]);
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_empty_matches() {
+        let ranges = |pattern: &str, s: &str| {
+            TinyRegex::new(pattern).unwrap().find_iter(s).map(|mat| mat.range()).collect::<Vec<_>>()
+        };
+
+        // a longer match wins over the empty one at the same position
+        let re = TinyRegex::new("a*").unwrap();
+        assert_eq!(re.find("aaa").unwrap().range(), 0..3);
+        assert_eq!(re.find("baaa").unwrap().range(), 0..0);
+        assert_eq!(re.find_at("baaa", 1).unwrap().range(), 1..4);
+        assert_eq!(re.find_at("baaa", 4).unwrap().range(), 4..4);
+        assert_eq!(TinyRegex::new("(b|)").unwrap().find("bb").unwrap().range(), 0..1);
+
+        // an empty match right after a match is skipped, and the search goes on a char later
+        assert_eq!(ranges("a*", "aaa"), [0..3]);
+        assert_eq!(ranges("a*", "baaa"), [0..0, 1..4]);
+        assert_eq!(ranges("a*", "bab"), [0..0, 1..2, 3..3]);
+        assert_eq!(ranges("a*", "baac"), [0..0, 1..3, 4..4]);
+        assert_eq!(ranges("a*", ""), [0..0]);
+        assert_eq!(ranges("(b|)", "xbb"), [0..0, 1..2, 2..3]);
+        assert_eq!(ranges("", "ab"), [0..0, 1..1, 2..2]);
+        assert_eq!(ranges("x*", "日本"), [0..0, 3..3, 6..6]);
+        assert_eq!(ranges(r"\b", "ab cd"), [0..0, 2..2, 3..3, 5..5]);
+        assert_eq!(ranges(r"(?m)^", "a\nb\n"), [0..0, 2..2, 4..4]);
+        assert_eq!(ranges(r"(?m)$", "a\nb"), [1..1, 3..3]);
+        assert_eq!(ranges("a|", "aba"), [0..1, 2..3]);
+        assert_eq!(ranges("a", ""), []);
+
+        // the upper bound covers every match left, and find_all agrees with find_iter
+        let re = TinyRegex::new("").unwrap();
+        let mut matches = re.find_all("abc");
+        assert_eq!(matches.size_hint(), (0, Some(4)));
+        matches.next();
+        assert_eq!(matches.size_hint(), (0, Some(3)));
+        assert_eq!(matches.count(), 3);
+    }
+
+    #[test]
+    fn test_greedy() {
+        let re = TinyRegex::new("a .* that is cute").unwrap();
+        let s = "I have a 🦀 that is cute. You have a 🐍 that is cute.";
+
+        let mut matches = re.find_all(s);
+        assert_eq!(matches.next().unwrap().as_str(), "a 🦀 that is cute. You have a 🐍 that is cute");
+        assert_eq!(matches.next(), None);
+    }
+
+    #[test]
+    fn test_escape_sequence() {
+        let re = TinyRegex::new(r".*\n").unwrap();
+        let s = "Hello\nWorld\n";
+
+        let mut matches = re.find_all(s);
+        assert_eq!(matches.next().unwrap().as_str(), "Hello\n");
+        assert_eq!(matches.next().unwrap().as_str(), "World\n");
+        assert_eq!(matches.next(), None);
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "on_the_fly")]
+mod tests {
+    use crate::{TinyRegex, Error};
+
+    #[test]
+    fn test_find() {
+        let re = TinyRegex::new("[a-zA-Z][a-zA-Z0-9]*").unwrap();
+        let s = "うにょ hello114514";
+        let mat = re.find(s).unwrap();
+
+        assert_eq!(10..21, mat.range());
+        assert_eq!("hello114514", &s[mat.range()]);
+        assert_eq!("hello114514", mat.as_str());
+    }
+
+    #[test]
+    fn test_for_readme() {
+        let re = TinyRegex::new("a(b|c)*d").unwrap();
+        assert!(re.is_match("abbbcd"));
+        assert!(!re.is_match("abbbce"));
+
+        let mat = re.find("wxyzabbbcdeffe").unwrap();
+        assert_eq!(mat.start(), 4);
+        assert_eq!(mat.end(), 10);
+        assert_eq!(mat.as_str(), "abbbcd");
+        assert_eq!(mat.range(), 4..10);
+    }
+
+    #[test]
+    fn test_find_all() {
+        let re = TinyRegex::new("[a-zA-Z][a-zA-Z]*").unwrap();
+        let s = "my name is Unyo";
+
+        let mut matches = re.find_all(s);
+        assert_eq!(matches.next().unwrap().as_str(), "my");
+        assert_eq!(matches.next().unwrap().as_str(), "name");
+        assert_eq!(matches.next().unwrap().as_str(), "is");
+        assert_eq!(matches.next().unwrap().as_str(), "Unyo");
+        assert_eq!(matches.next(), None);
+    }
+
+    #[test]
+    fn test_negchar() {
+        let re = TinyRegex::new("[^・ー]").unwrap();
+        let s = "エドワード・ノートン\n";
+
+        let mut matches = re.find_all(s);
+        assert_eq!(matches.next().unwrap().as_str(), "エ");
+        assert_eq!(matches.next().unwrap().as_str(), "ド");
+        assert_eq!(matches.next().unwrap().as_str(), "ワ");
+        assert_eq!(matches.next().unwrap().as_str(), "ド");
+        assert_eq!(matches.next().unwrap().as_str(), "ノ");
+        assert_eq!(matches.next().unwrap().as_str(), "ト");
+        assert_eq!(matches.next().unwrap().as_str(), "ン");
+        assert_eq!(matches.next().unwrap().as_str(), "\n");
+        assert_eq!(matches.next(), None);
+    }
+
+    #[test]
+    fn test_any_char() {
+        let re = TinyRegex::new(" ... ").unwrap();
+        let s = "Das Alter beginnt in dem Augenblick, wo man nicht mehr ohne die Vergangenheit leben kann.";
+
+        let mut matches = re.find_all(s);
+        assert_eq!(matches.next().unwrap().as_str(), " dem ");
+        assert_eq!(matches.next().unwrap().as_str(), " man ");
+        assert_eq!(matches.next().unwrap().as_str(), " die ");
+        assert_eq!(matches.next(), None);
+    }
+
+    #[test]
+    fn test_plus_and_question() {
+        let re = TinyRegex::new("[0-9]+(:[0-9]+)?").unwrap();
+        let s = "meet at 10:30 or 9:05, room 42";
+
+        let mut matches = re.find_all(s);
+        assert_eq!(matches.next().unwrap().as_str(), "10:30");
+        assert_eq!(matches.next().unwrap().as_str(), "9:05");
+        assert_eq!(matches.next().unwrap().as_str(), "42");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new("colou?r").unwrap();
+        assert!(re.is_match("color"));
+        assert!(re.is_match("colour"));
+        assert!(!re.is_match("colouur"));
+    }
+
+    #[test]
+    fn test_stacked_quantifiers() {
+        let re = TinyRegex::new("x(ab)+*y").unwrap();
+        assert!(re.is_match("xy"));
+        assert!(re.is_match("xababy"));
+        assert!(!re.is_match("xay"));
+
+        let re = TinyRegex::new("xa?+y").unwrap();
+        assert!(re.is_match("xy"));
+        assert!(re.is_match("xaaay"));
+
+        assert!(TinyRegex::new("+a").is_err());
+        assert!(TinyRegex::new("a|?").is_err());
+        assert!(TinyRegex::new("(*)").is_err());
+
+        // a `?` after a quantifier would make it lazy, and is rejected at the `?`
+        for (pattern, span) in [("a+?", 2..3), ("a*?", 2..3), ("a??", 2..3), ("a{2}?", 4..5), (r"\d+?", 3..4), ("x(ab)*?y", 6..7)] {
+            let err = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(err, Error::UnexpectedToken(_)), "{}", pattern);
+            assert_eq!(err.span(), span, "{}", pattern);
+        }
+        assert!(TinyRegex::new("a?+").is_ok());
+        assert!(TinyRegex::new("(a+)?").is_ok());
+    }
+
+    #[test]
+    fn test_counted_repetition() {
+        let re = TinyRegex::new("[0-9]{3} [0-9]{4}").unwrap();
+        assert_eq!(re.find("zip: 100 0001").unwrap().as_str(), "100 0001");
+        assert!(!re.is_match("zip: 10 0001"));
+
+        let re = TinyRegex::new("x(ab){2,3}y").unwrap();
+        assert!(!re.is_match("xaby"));
+        assert!(re.is_match("xababy"));
+        assert!(re.is_match("xabababy"));
+        assert!(!re.is_match("xababababy"));
+
+        let re = TinyRegex::new("xa{2,}y").unwrap();
+        assert!(!re.is_match("xay"));
+        assert!(re.is_match("xaaaaay"));
+
+        let re = TinyRegex::new("a{,2}").unwrap();
+        assert_eq!(re.find("a{,2}").unwrap().as_str(), "a{,2}");
+
+        assert!(TinyRegex::new("a{3,2}").is_err());
+        assert!(TinyRegex::new("{2}").is_err());
+    }
+
+    #[test]
+    fn test_size_limit() {
+        assert!(TinyRegex::new("a{1000}{1000}").is_err());
+        assert!(TinyRegex::builder("a{100}").size_limit(100).build().is_err());
+        assert!(TinyRegex::builder("a{10}").size_limit(100).build().is_ok());
+
+        // a counted repetition is as large as the copies spelled out
+        let literal = "a".repeat(20);
+        for pattern in ["a{20}", &literal] {
+            assert!(TinyRegex::builder(pattern).size_limit(42).build().is_ok(), "{}", pattern);
+            assert!(matches!(TinyRegex::builder(pattern).size_limit(41).build().err(), Some(Error::TooLarge(_))), "{}", pattern);
+        }
+        assert!(TinyRegex::builder("(ab){2,3}").size_limit(28).build().is_ok());
+        assert!(TinyRegex::builder("(ab){2,3}").size_limit(27).build().is_err());
+
+        // the on-the-fly DFA builds the states during the search, so an exponential one is not built up front
+        let re = TinyRegex::builder("[ab]*a[ab]{20}").dfa_size_limit(1_000).build().unwrap();
+        assert_eq!(re.find(&format!("x{}y", "ab".repeat(11))).unwrap().range(), 1..22);
+    }
+
+    #[test]
+    fn test_anchors() {
+        let re = TinyRegex::new("^[0-9]+").unwrap();
+        assert_eq!(re.find("123abc456").unwrap().as_str(), "123");
+        assert!(!re.is_match("abc456"));
+        assert_eq!(re.find_at("123abc456", 6), None);
+
+        let re = TinyRegex::new("[0-9]+$").unwrap();
+        assert_eq!(re.find("123abc456").unwrap().range(), 6..9);
+        assert!(!re.is_match("123abc"));
+
+        let re = TinyRegex::new(r"\Aうにょ*\z").unwrap();
+        assert!(re.is_match("うにょょょ"));
+        assert!(!re.is_match("うにょ~"));
+        assert!(!re.is_match(" うにょ"));
+
+        let re = TinyRegex::new("(^|,)ab(,|$)").unwrap();
+        assert_eq!(re.find("ab,").unwrap().range(), 0..3);
+        assert_eq!(re.find("xab,ab").unwrap().range(), 3..6);
+        assert!(!re.is_match("xabx"));
+
+        let re = TinyRegex::new("$").unwrap();
+        assert_eq!(re.find("abc").unwrap().range(), 3..3);
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        let re = TinyRegex::new(r"\bcat\b").unwrap();
+        assert_eq!(re.find("concat cat catalog").unwrap().range(), 7..10);
+        assert!(!re.is_match("concat catalog"));
+
+        let re = TinyRegex::new(r"\Bcat").unwrap();
+        assert_eq!(re.find("cat concat").unwrap().range(), 7..10);
+
+        let re = TinyRegex::new(r"\b[^・]+\b").unwrap();
+        let s = "エドワード・ノートン\n";
+
+        let mut matches = re.find_all(s);
+        assert_eq!(matches.next().unwrap().as_str(), "エドワード");
+        assert_eq!(matches.next().unwrap().as_str(), "ノートン");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"\Bトン\b").unwrap();
+        assert_eq!(re.find("トン ノートン").unwrap().as_str(), "トン");
+        assert_eq!(re.find("トン ノートン").unwrap().start(), 13);
+
+        // without Unicode, the word chars are `[0-9A-Za-z_]` as for `\w`
+        let re = TinyRegex::builder(r"\bfe\b").unicode(false).build().unwrap();
+        assert_eq!(re.find("éfe fe").unwrap().range(), 2..4);
+        assert_eq!(TinyRegex::new(r"\bfe\b").unwrap().find("éfe fe").unwrap().range(), 5..7);
+        let re = TinyRegex::builder(r"\Bé").unicode(false).build().unwrap();
+        assert!(!re.is_match("café"));
+        assert!(TinyRegex::new(r"\Bé").unwrap().is_match("café"));
+    }
+
+    #[test]
+    fn test_escaped_meta_character() {
+        let re = TinyRegex::new(r"\(\*\)\.").unwrap();
+        assert_eq!(re.find("f(*).").unwrap().range(), 1..5);
+        assert!(TinyRegex::new(r"\q").is_err());
+    }
+
+    #[test]
+    fn test_perl_classes() {
+        let re = TinyRegex::new(r"\d+").unwrap();
+        let mut matches = re.find_all("abc١٢٣ 456");
+        assert_eq!(matches.next().unwrap().as_str(), "١٢٣");
+        assert_eq!(matches.next().unwrap().as_str(), "456");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::builder(r"\d+").unicode(false).build().unwrap();
+        let mut matches = re.find_all("abc١٢٣ 456");
+        assert_eq!(matches.next().unwrap().as_str(), "456");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"\w+").unwrap();
+        assert_eq!(re.find("・ノートン・").unwrap().as_str(), "ノートン");
+
+        let re = TinyRegex::new(r"\S+\s\D").unwrap();
+        assert_eq!(re.find("12 34\u{3000}ab").unwrap().as_str(), "34\u{3000}a");
+    }
+
+    #[test]
+    fn test_perl_classes_in_brackets() {
+        let re = TinyRegex::builder(r"[\w-]+").unicode(false).build().unwrap();
+        let mut matches = re.find_all("tiny-regex, tiny_grep");
+        assert_eq!(matches.next().unwrap().as_str(), "tiny-regex");
+        assert_eq!(matches.next().unwrap().as_str(), "tiny_grep");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::builder(r"[^\W_]+").unicode(false).build().unwrap();
+        let mut matches = re.find_all("snake_case");
+        assert_eq!(matches.next().unwrap().as_str(), "snake");
+        assert_eq!(matches.next().unwrap().as_str(), "case");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"[^\s,]+").unwrap();
+        let mut matches = re.find_all("a,b c");
+        assert_eq!(matches.next().unwrap().as_str(), "a");
+        assert_eq!(matches.next().unwrap().as_str(), "b");
+        assert_eq!(matches.next().unwrap().as_str(), "c");
+        assert_eq!(matches.next(), None);
+    }
+
+    #[test]
+    fn test_posix_classes() {
+        let re = TinyRegex::new("[[:xdigit:]]+").unwrap();
+        let mut matches = re.find_all("0xDEADbeef");
+        assert_eq!(matches.next().unwrap().as_str(), "0");
+        assert_eq!(matches.next().unwrap().as_str(), "DEADbeef");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new("[[:upper:]][[:lower:][:digit:]]*").unwrap();
+        assert_eq!(re.find("the Ωmega42 release").unwrap().as_str(), "Ωmega42");
+
+        let re = TinyRegex::builder("[[:alpha:]_][[:alnum:]_]*").unicode(false).build().unwrap();
+        assert_eq!(re.find("3 + _x1 * é").unwrap().as_str(), "_x1");
+
+        let re = TinyRegex::new("[^[:space:][:punct:]]+").unwrap();
+        let mut matches = re.find_all("Hello, 世界!");
+        assert_eq!(matches.next().unwrap().as_str(), "Hello");
+        assert_eq!(matches.next().unwrap().as_str(), "世界");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new("[^[:^digit:]5]+").unwrap();
+        assert_eq!(re.find("a12345678").unwrap().as_str(), "1234");
+
+        assert!(TinyRegex::new("[[:vowel:]]").is_err());
+    }
+
+    #[test]
+    fn test_unicode_classes() {
+        let re = TinyRegex::new(r"\p{Greek}+").unwrap();
+        assert_eq!(re.find("alpha is αλφα").unwrap().as_str(), "αλφα");
+
+        let re = TinyRegex::new(r"\p{Script=Hiragana}+").unwrap();
+        let mut matches = re.find_all("ひらがなとカタカナ");
+        assert_eq!(matches.next().unwrap().as_str(), "ひらがなと");
+        assert_eq!(matches.next(), None);
+
+        let re = TinyRegex::new(r"\p{Lu}\p{Ll}+").unwrap();
+        assert_eq!(re.find("ΑΒ Ωmega").unwrap().as_str(), "Ωmega");
+
+        let re = TinyRegex::new(r"[\p{sc=Katakana}ー・]+").unwrap();
+        assert_eq!(re.find("俳優のエドワード・ノートン").unwrap().as_str(), "エドワード・ノートン");
+
+        let re = TinyRegex::new(r"\P{Han}+").unwrap();
+        assert_eq!(re.find("俳優のエドワード").unwrap().as_str(), "のエドワード");
+
+        let re = TinyRegex::new(r"[^\p{^Nd}5]+").unwrap();
+        assert_eq!(re.find("x١٢٣45").unwrap().as_str(), "١٢٣4");
+
+        assert!(TinyRegex::new(r"\pN").unwrap().is_match("Ⅻ"));
+        assert!(TinyRegex::new(r"\p{Klingon}").is_err());
+        assert!(TinyRegex::new(r"\p{Greek").is_err());
+    }
+
+    #[test]
+    fn test_numeric_escapes() {
+        let re = TinyRegex::new(r"\x41\x{3b1}\u{1F600}\u00E9").unwrap();
+        assert_eq!(re.find("xAα😀é").unwrap().as_str(), "Aα😀é");
+
+        let re = TinyRegex::new(r"[\x30-\x39]+\o{41}").unwrap();
+        assert_eq!(re.find("abc123!").unwrap().as_str(), "123!");
+
+        let re = TinyRegex::new(r"\011\0\cA\cj\c?").unwrap();
+        assert!(re.is_match("\t\0\x01\n\x7F"));
+
+        assert!(TinyRegex::new(r"\x4").is_err());
+        assert!(TinyRegex::new(r"\x{}").is_err());
+        assert!(TinyRegex::new(r"\x{41").is_err());
+        assert!(TinyRegex::new(r"\x{110000}").is_err());
+        assert!(TinyRegex::new(r"\x{FFFFFFFFFF}").is_err());
+        assert!(TinyRegex::new(r"\u{D800}").is_err());
+        assert!(TinyRegex::new(r"\o{8}").is_err());
+        assert!(TinyRegex::new(r"\c1").is_err());
+    }
+
+    #[test]
+    fn test_captures() {
+        let re = TinyRegex::new(r"(\d{4}),(\d{2}),(\d{2}) (\w+)").unwrap();
+        assert_eq!(re.captures_len(), 5);
+        let caps = re.captures("date: 2024,01,31 tuesday").unwrap();
+        assert_eq!(caps.len(), 5);
+        assert_eq!(caps.get(0).unwrap().as_str(), "2024,01,31 tuesday");
+        assert_eq!(caps.get(1).unwrap().as_str(), "2024");
+        assert_eq!(caps.get(2).unwrap().range(), 11..13);
+        assert_eq!(caps.get(3).unwrap().as_str(), "31");
+        assert_eq!(caps.get(4).unwrap().as_str(), "tuesday");
+        assert_eq!(caps.get(5), None);
+        assert!(re.captures("no date").is_none());
+
+        // the groups follow the leftmost-longest match the DFA finds
+        let re = TinyRegex::new(r"(a|ab)(c|bcd)(d*)").unwrap();
+        let caps = re.captures("abcd").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "abcd");
+        assert_eq!(caps.get(1).unwrap().as_str(), "a");
+        assert_eq!(caps.get(2).unwrap().as_str(), "bcd");
+        assert_eq!(caps.get(3).unwrap().as_str(), "");
+
+        // greedy groups take as much as the match allows, and the last iteration wins
+        let re = TinyRegex::new(r"(\w+)(\d+)").unwrap();
+        let caps = re.captures("abc123").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "abc12");
+        assert_eq!(caps.get(2).unwrap().as_str(), "3");
+
+        let re = TinyRegex::new(r"((ア|イ)ー)+").unwrap();
+        let caps = re.captures("アーイーウ").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "アーイー");
+        assert_eq!(caps.get(1).unwrap().as_str(), "イー");
+        assert_eq!(caps.get(2).unwrap().as_str(), "イ");
+
+        // a group not taking part in the match
+        let re = TinyRegex::new(r"(x)?\b(y)").unwrap();
+        let caps = re.captures("z y").unwrap();
+        assert_eq!(caps.get(1), None);
+        assert_eq!(caps.get(2).unwrap().start(), 2);
+    }
+
+    #[test]
+    fn test_named_groups() {
+        let re = TinyRegex::new(r"(?P<key>\w+)=(?:(?<quoted>'[^']*')|(?P<bare>\w*))").unwrap();
+        assert_eq!(re.captures_len(), 4);
+        assert_eq!(re.capture_names().collect::<Vec<_>>(), vec![None, Some("key"), Some("quoted"), Some("bare")]);
+
+        let caps = re.captures("set name='tiny regex'").unwrap();
+        assert_eq!(caps.name("key").unwrap().as_str(), "name");
+        assert_eq!(caps.name("quoted").unwrap().as_str(), "'tiny regex'");
+        assert_eq!(caps.get(2).unwrap().as_str(), "'tiny regex'");
+        assert_eq!(caps.name("bare"), None);
+        assert_eq!(caps.name("value"), None);
+
+        let caps = re.captures("set size=42").unwrap();
+        assert_eq!(caps.name("bare").unwrap().as_str(), "42");
+
+        // non-capturing groups do not shift the numbering
+        let re = TinyRegex::new(r"(?:ア|イ)+(ウ)").unwrap();
+        assert_eq!(re.capture_names().collect::<Vec<_>>(), vec![None, None]);
+        assert_eq!(re.captures("アイウ").unwrap().get(1).unwrap().as_str(), "ウ");
+
+        assert!(TinyRegex::new(r"(?P<a>x)(?P<a>y)").is_err());
+        assert!(TinyRegex::new(r"(?P<>x)").is_err());
+        assert!(TinyRegex::new(r"(?P<1a>x)").is_err());
+        assert!(TinyRegex::new(r"(?P<a x)").is_err());
+        assert!(TinyRegex::new(r"(?=x)").is_err());
+    }
+
+    #[test]
+    fn test_inline_flags() {
+        let re = TinyRegex::new(r"(?i)tiny regex").unwrap();
+        assert_eq!(re.find("This is TiNy ReGeX").unwrap().as_str(), "TiNy ReGeX");
+
+        let re = TinyRegex::new(r"(?i)[a-c]+|[α-γ]+").unwrap();
+        assert_eq!(re.find("xyzaBC").unwrap().as_str(), "aBC");
+        assert_eq!(re.find("ΑΒΓ").unwrap().as_str(), "ΑΒΓ");
+
+        // the flags are scoped by the enclosing group
+        let re = TinyRegex::new(r"a(?i:b)c((?i)d)e").unwrap();
+        assert!(re.is_match("aBcDe"));
+        assert!(!re.is_match("aBCDe"));
+        assert!(!re.is_match("aBcDE"));
+
+        let re = TinyRegex::new(r"(?i)a(?-i)b").unwrap();
+        assert!(re.is_match("Ab"));
+        assert!(!re.is_match("AB"));
+
+        // multi-line anchors match at line feeds
+        let re = TinyRegex::new(r"(?m)^\w+$").unwrap();
+        let mut matches = re.find_all("first\nsecond line\nthird");
+        assert_eq!(matches.next().unwrap().as_str(), "first");
+        assert_eq!(matches.next().unwrap().as_str(), "third");
+        assert_eq!(matches.next(), None);
+        assert_eq!(TinyRegex::new(r"^\w+$").unwrap().find("first\nthird"), None);
+        assert!(TinyRegex::new(r"(?m)\A\w+$").unwrap().find("a\nb").is_some_and(|mat| mat.as_str() == "a"));
+
+        // dot-matches-newline can be cleared
+        let re = TinyRegex::new(r"(?-s)a.+").unwrap();
+        assert_eq!(re.find("xab\nc").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::new(r"(?ims)^a.b$").unwrap();
+        assert!(re.is_match("x\nA\nB"));
+
+        assert!(TinyRegex::new(r"(?q)a").is_err());
+        assert!(TinyRegex::new(r"(?i-m-s)a").is_err());
+        assert!(TinyRegex::new(r"(?)a").is_err());
+        assert!(TinyRegex::new(r"(?i").is_err());
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let re = TinyRegex::new(r"(?i)straße").unwrap();
+        assert!(re.is_match("STRAẞE"));
+        assert!(!re.is_match("STRASSE"));
+
+        let re = TinyRegex::new(r"(?i)σοφία").unwrap();
+        assert!(re.is_match("ΣΟΦΊΑ"));
+        assert!(TinyRegex::new(r"(?i)λόγος").unwrap().is_match("ΛΌΓΟΣ"));
+
+        // the Kelvin sign and the long s fold to ASCII letters
+        let re = TinyRegex::new(r"(?i)^[a-z]+$").unwrap();
+        assert!(re.is_match("Kelvin"));
+        assert!(re.is_match("\u{212A}elvin"));
+        assert!(re.is_match("\u{17F}"));
+
+        // the chars excluded by a negated class are folded before the negation
+        let re = TinyRegex::new(r"(?i)[^a-z]").unwrap();
+        assert!(!re.is_match("A"));
+        assert!(!re.is_match("\u{212A}"));
+        assert!(!re.is_match("\u{17F}"));
+        assert!(re.is_match("1"));
+
+        let re = TinyRegex::new(r"(?i)[^\Wk]").unwrap();
+        assert!(re.is_match("j"));
+        assert!(!re.is_match("K"));
+        assert!(!re.is_match("\u{212A}"));
+
+        let re = TinyRegex::new(r"(?i)[^\p{Lu}]").unwrap();
+        assert!(!re.is_match("a"));
+        assert!(re.is_match("1"));
+
+        let re = TinyRegex::new(r"(?i)\p{Lu}").unwrap();
+        assert!(re.is_match("a"));
+        assert!(!re.is_match("1"));
+    }
+
+    #[test]
+    fn test_dot_and_new_lines() {
+        let re = TinyRegex::new(r"a.*").unwrap();
+        assert_eq!(re.find("xab\ncd").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::builder(r"a.*").dot_matches_new_line(true).build().unwrap();
+        assert_eq!(re.find("xab\ncd").unwrap().as_str(), "ab\ncd");
+        assert_eq!(TinyRegex::new(r"(?s)a.*").unwrap().find("xab\ncd").unwrap().as_str(), "ab\ncd");
+        assert_eq!(TinyRegex::new(r"(?s:a.)+").unwrap().find("a\na\n").unwrap().as_str(), "a\na\n");
+
+        // `.` still matches `\r` unless in CRLF mode
+        assert_eq!(TinyRegex::new(r"a.*").unwrap().find("ab\r\n").unwrap().as_str(), "ab\r");
+        let re = TinyRegex::builder(r"a.*").crlf(true).build().unwrap();
+        assert_eq!(re.find("ab\r\n").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::new(r"(?mR)^\w+$").unwrap();
+        let mut matches = re.find_all("first\r\nsecond\rthird\n");
+        assert_eq!(matches.next().unwrap().as_str(), "first");
+        assert_eq!(matches.next().unwrap().as_str(), "second");
+        assert_eq!(matches.next().unwrap().as_str(), "third");
+
+        // never between `\r` and `\n`
+        let re = TinyRegex::new(r"(?mR)^$").unwrap();
+        assert!(!re.is_match("a\r\nb"));
+        assert!(re.is_match("a\r\n\r\nb"));
+        assert!(!TinyRegex::new(r"(?m)^$").unwrap().is_match("a\r\nb"));
+        assert!(TinyRegex::new(r"(?m)\r$").unwrap().is_match("a\r\nb"));
+    }
+
+    #[test]
+    fn test_verbose() {
+        let re = TinyRegex::new(r"(?x)
+            (?P<year> \d{4} ) ,   # the year
+            (?P<month> \d{2} )    # the month
+            \ \#                  # escaped whitespace and number sign
+        ").unwrap();
+        let caps = re.captures("on 2024,01 #").unwrap();
+        assert_eq!(caps.name("year").unwrap().as_str(), "2024");
+        assert_eq!(caps.name("month").unwrap().as_str(), "01");
+        assert_eq!(caps.get(0).unwrap().as_str(), "2024,01 #");
+
+        // whitespace inside brackets is kept
+        let re = TinyRegex::new(r"(?x) [ #]+ ").unwrap();
+        assert_eq!(re.find("a # b").unwrap().as_str(), " # ");
+
+        // verbose mode is scoped like the other flags
+        let re = TinyRegex::new(r"(?x: a b ) c d").unwrap();
+        assert!(re.is_match("ab c d"));
+        assert!(!re.is_match("abcd"));
+        let re = TinyRegex::new(r"a b(?x) c d").unwrap();
+        assert!(re.is_match("a bcd"));
+        let re = TinyRegex::new(r"((?x) a b ) c").unwrap();
+        assert!(re.is_match("ab c"));
+
+        // whitespace around the counts of a repetition is ignored too, but not outside verbose mode
+        let re = TinyRegex::new(r"(?x)a{2, 3}").unwrap();
+        assert_eq!(re.find("baaaab").unwrap().as_str(), "aaa");
+        assert!(!re.is_match("a{2,3}"));
+        let re = TinyRegex::new(r"(?x)a{ 2 }").unwrap();
+        assert_eq!(re.find("baaab").unwrap().as_str(), "aa");
+        let re = TinyRegex::new(r"a{2, 3}").unwrap();
+        assert!(re.is_match("a{2, 3}"));
+        assert!(!re.is_match("aa"));
+    }
+
+    #[test]
+    fn test_quote() {
+        let re = TinyRegex::new(r"\Q1+1=(2)\E!*").unwrap();
+        assert_eq!(re.find("so 1+1=(2)!!").unwrap().as_str(), "1+1=(2)!!");
+        assert!(!re.is_match("11=2"));
+
+        let re = TinyRegex::new(r"a\Q.*").unwrap();
+        assert!(re.is_match("a.*"));
+        assert!(!re.is_match("abc"));
+
+        let re = TinyRegex::new(r"(?x) \Q# not a comment\E").unwrap();
+        assert!(re.is_match("# not a comment"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(crate::escape("a.b*c"), r"a\.b\*c");
+        assert_eq!(crate::escape("[ア-ン]"), r"\[ア\-ン\]");
+
+        // a pseudo-random generator, so that the strings are the same every run
+        let pool = "ab .+*?()|[]{}^$#&-~\\\n\t/:エ😀";
+        let pool = pool.chars().collect::<Vec<char>>();
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..200 {
+            let len = next() % 12;
+            let s = (0..len).map(|_| pool[(next() % pool.len() as u64) as usize]).collect::<String>();
+            let re = TinyRegex::new(&crate::escape(&s)).unwrap();
+            assert_eq!(re.find(&s).unwrap().as_str(), s);
+        }
+    }
+
+    #[test]
+    fn test_class_lexing() {
+        let re = TinyRegex::new(r"[.*+?()|{}$]+").unwrap();
+        assert_eq!(re.find("ab(a+b)*{c}$").unwrap().as_str(), "(");
+        assert_eq!(re.find("x.*+?()|{}$y").unwrap().as_str(), ".*+?()|{}$");
+
+        let re = TinyRegex::new(r"[]a]+").unwrap();
+        assert_eq!(re.find("b]a]c").unwrap().as_str(), "]a]");
+        let re = TinyRegex::new(r"[^]a]+").unwrap();
+        assert_eq!(re.find("]a]bc]").unwrap().as_str(), "bc");
+
+        let re = TinyRegex::new(r"[a-]+").unwrap();
+        assert_eq!(re.find("b-a-c").unwrap().as_str(), "-a-");
+        let re = TinyRegex::new(r"[-a]+").unwrap();
+        assert_eq!(re.find("b-a-c").unwrap().as_str(), "-a-");
+        let re = TinyRegex::new(r"[^-a]+").unwrap();
+        assert_eq!(re.find("-a-bc-").unwrap().as_str(), "bc");
+
+        // `^` is literal except as the first char
+        let re = TinyRegex::new(r"[a^]+").unwrap();
+        assert_eq!(re.find("b^a^c").unwrap().as_str(), "^a^");
+        let re = TinyRegex::new(r"[^^]+").unwrap();
+        assert_eq!(re.find("^^ab^").unwrap().as_str(), "ab");
+
+        // `(` and `#` inside brackets neither open a group nor start a comment
+        let re = TinyRegex::new(r"(?x) [(#] + ").unwrap();
+        assert_eq!(re.find("a(#b").unwrap().as_str(), "(#");
+
+        // `-` and `]` outside brackets are ordinary characters
+        let re = TinyRegex::new(r"\d+-\d+]").unwrap();
+        assert_eq!(re.find("[12-34]").unwrap().as_str(), "12-34]");
+
+        assert!(TinyRegex::new(r"[]").is_err());
+        assert!(TinyRegex::new(r"[^]").is_err());
+    }
+
+    #[test]
+    fn test_class_set_operations() {
+        let re = TinyRegex::new(r"[\p{L}--[aeiou]]+").unwrap();
+        assert_eq!(re.find("aeiXyzéo").unwrap().as_str(), "Xyzé");
+
+        let re = TinyRegex::new(r"[\p{Greek}&&\p{Ll}]+").unwrap();
+        assert_eq!(re.find("ΑΒγδΕ").unwrap().as_str(), "γδ");
+
+        // nested classes are unions, and `&&` and `--` bind looser and associate to the left
+        let re = TinyRegex::new(r"[a[bc]]+").unwrap();
+        assert_eq!(re.find("xcabd").unwrap().as_str(), "cab");
+        let re = TinyRegex::new(r"[a-z&&[^aeiou]--xyz]+").unwrap();
+        assert_eq!(re.find("xbcdefy").unwrap().as_str(), "bcd");
+        let re = TinyRegex::new(r"[^[a-z]--[aeiou]]+").unwrap();
+        assert_eq!(re.find("bcaeXd").unwrap().as_str(), "aeX");
+        let re = TinyRegex::new(r"[\w--\d]+").unwrap();
+        assert_eq!(re.find("12ab_3").unwrap().as_str(), "ab_");
+        let re = TinyRegex::new(r"[^\W&&\D]+").unwrap();
+        assert_eq!(re.find("ab12_-").unwrap().as_str(), "ab12_");
+
+        let re = TinyRegex::new(r"(?i)[a-z--[aeiou]]+").unwrap();
+        assert_eq!(re.find("AEBCdE").unwrap().as_str(), "BCd");
+
+        // a single `&` and a `-` at either end are literal
+        let re = TinyRegex::new(r"[a&-]+").unwrap();
+        assert_eq!(re.find("b&-a").unwrap().as_str(), "&-a");
+
+        assert!(TinyRegex::new(r"[a[bc]").is_err());
+
+        // the `]` right after `&&` or `--` closes the class, leaving the operator without an operand
+        for (pattern, span) in [(r"[a&&]", 2..4), (r"[a--]", 2..4), (r"[+&&]", 2..4), (r"x[[ab]--]y", 6..8)] {
+            let e = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(e, Error::UnexpectedToken(_)), "{}", pattern);
+            assert_eq!(e.span(), span, "{}", pattern);
+        }
+        let spans = crate::check(r"[a--]b)").iter().map(|e| e.span()).collect::<Vec<_>>();
+        assert_eq!(spans, [2..4, 6..7]);
+    }
+
+    #[test]
+    fn test_large_classes() {
+        let re = TinyRegex::new(r"[\u{4E00}-\u{9FFF}\u{20000}-\u{2A6DF}]+").unwrap();
+        assert_eq!(re.find("kanji: 漢字𠀋です").unwrap().as_str(), "漢字𠀋");
+
+        let re = TinyRegex::new(r"[\x{0}-\x{D7FF}\x{E000}-\x{10FFFF}]").unwrap();
+        assert_eq!(re.find("😀").unwrap().as_str(), "😀");
+        let re = TinyRegex::new(r"a[^\x{0}-\x{D7FF}\x{E000}-\x{10FFFF}]").unwrap();
+        assert!(re.find("a😀").is_none());
+
+        let re = TinyRegex::new(r"[^a]+").unwrap();
+        assert_eq!(re.find("aエ😀\u{10FFFF}a").unwrap().as_str(), "エ😀\u{10FFFF}");
+
+        let re = TinyRegex::new(r"(?i)\b[\p{L}--\p{Han}]+\b").unwrap();
+        assert_eq!(re.find("漢字 Ωmega").unwrap().as_str(), "Ωmega");
+    }
+
+    #[test]
+    fn test_empty() {
+        let re = TinyRegex::new("").unwrap();
+        assert_eq!(re.find("abc").unwrap().range(), 0..0);
+        assert!(re.is_match(""));
+        assert_eq!(re.find("\0").unwrap().range(), 0..0);
+
+        let re = TinyRegex::new("a|").unwrap();
+        assert_eq!(re.find("ba").unwrap().range(), 0..0);
+        assert_eq!(re.find("ab").unwrap().as_str(), "a");
+        let re = TinyRegex::new("|a").unwrap();
+        assert_eq!(re.find("ab").unwrap().as_str(), "a");
+
+        let re = TinyRegex::new("(|b)c").unwrap();
+        assert_eq!(re.find("abc").unwrap().as_str(), "bc");
+        assert_eq!(re.find("ac").unwrap().as_str(), "c");
+        let caps = re.captures("ac").unwrap();
+        assert_eq!(caps.get(1).unwrap().range(), 1..1);
+
+        let re = TinyRegex::new("a()*b").unwrap();
+        assert_eq!(re.find("xab").unwrap().as_str(), "ab");
+        let re = TinyRegex::new("a(?:)b").unwrap();
+        assert_eq!(re.find("xab").unwrap().as_str(), "ab");
+
+        let re = TinyRegex::new("ab{0}c").unwrap();
+        assert_eq!(re.find("abcac").unwrap().as_str(), "ac");
+    }
+
+    #[test]
+    fn test_errors() {
+        let spans = [
+            ("a(b|c", 1..2),
+            ("ab)c", 2..3),
+            ("x[a-z", 1..2),
+            ("[a[b]", 0..1),
+            ("a{3,2}b", 1..6),
+            ("[a-\\d]", 1..5),
+            ("ab\\", 2..3),
+            ("a\\qb", 1..3),
+            ("\\x{110000}", 0..10),
+            ("(?x) \\p{Klingon}", 5..16),
+            ("[[:vowel:]]", 1..10),
+            ("(?P<1a>x)", 0..7),
+            ("(?P<a>x)(?P<a>y)", 8..14),
+            ("(?q)", 0..3),
+            ("a|*", 2..3),
+            ("a{100}", 1..6)
+        ];
+        for (pattern, span) in spans {
+            let e = TinyRegex::builder(pattern).size_limit(100).build().err().unwrap();
+            assert_eq!(e.span(), span, "{}", pattern);
+            assert_eq!(e.pattern(), pattern);
+        }
+
+        assert!(matches!(TinyRegex::new("a(b|c").err(), Some(Error::UnclosedGroup(_))));
+        assert!(matches!(TinyRegex::new("ab)c").err(), Some(Error::UnopenedGroup(_))));
+        assert!(matches!(TinyRegex::new("[a-z").err(), Some(Error::UnclosedClass(_))));
+        assert!(matches!(TinyRegex::new("a{3,2}").err(), Some(Error::BadRange(_))));
+        assert!(matches!(TinyRegex::new("ab\\").err(), Some(Error::DanglingEscape(_))));
+        assert!(matches!(TinyRegex::new("a\\qb").err(), Some(Error::BadEscape(_))));
+        assert!(matches!(TinyRegex::new("\\p{Klingon}").err(), Some(Error::UnknownClass(_))));
+        assert!(matches!(TinyRegex::new("(?q)").err(), Some(Error::BadGroup(_))));
+        assert!(matches!(TinyRegex::new("+a").err(), Some(Error::DanglingQuantifier(_))));
+        assert!(matches!(TinyRegex::builder("a{100}").size_limit(100).build().err(), Some(Error::TooLarge(_))));
+
+        let e = TinyRegex::new("ab\\qc").err().unwrap();
+        assert_eq!(e.to_string(), "unknown escape sequence \\q\nab\\qc\n  ^^");
+        let e = TinyRegex::new("(?x)\n\ta\\q  # comment").err().unwrap();
+        assert_eq!(e.to_string(), "unknown escape sequence \\q\n\ta\\q  # comment\n\t ^^");
+        let e = TinyRegex::new("(ab").err().unwrap();
+        assert_eq!(e.to_string(), "( is not closed by )\n(ab\n^");
+        let e: Box<dyn std::error::Error> = Box::new(TinyRegex::new("(?").err().unwrap());
+        assert!(e.to_string().starts_with("(? is not closed"));
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        let spans = [
+            ("ab[z-a]", 3..6),
+            ("[^c-a]", 2..5),
+            ("[\\x{10FFFF}-\\0]", 1..14),
+            ("[\\d-z]", 1..5),
+            ("[a-\\d]", 1..5),
+            ("[[:alpha:]-z]", 1..12),
+            ("[a-[bc]]", 1..4),
+            ("[a-c-e]", 4..5)
+        ];
+        for (pattern, span) in spans {
+            let e = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(e, Error::BadRange(_)), "{}", pattern);
+            assert_eq!(e.span(), span, "{}", pattern);
+        }
+
+        // a range may neither span the surrogates, which are not chars, nor end at a surrogate
+        for (pattern, span) in [(r"[\u{D7FF}-\u{E000}]", 1..18), (r"[a\0-\x{10FFFF}]", 2..15)] {
+            let e = TinyRegex::new(pattern).err().unwrap();
+            assert!(matches!(e, Error::BadRange(_)), "{}", pattern);
+            assert_eq!(e.span(), span, "{}", pattern);
+        }
+        let re = TinyRegex::new(r"[\u{D000}-\u{D7FF}\u{E000}-\u{E0FF}]+").unwrap();
+        assert_eq!(re.find("a\u{D7FF}\u{E000}b").unwrap().as_str(), "\u{D7FF}\u{E000}");
+        let e = TinyRegex::new(r"[\u{D000}-\u{D800}]").err().unwrap();
+        assert!(matches!(e, Error::BadEscape(_)));
+        assert_eq!(e.span(), 10..18);
+
+        // `-` is literal at either end and after a class right before `]`
+        let re = TinyRegex::new(r"[\d-]+").unwrap();
+        assert_eq!(re.find("a1-2b").unwrap().as_str(), "1-2");
+        let re = TinyRegex::new(r"[a-a]+").unwrap();
+        assert_eq!(re.find("baab").unwrap().as_str(), "aa");
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(crate::check(r"(?P<y>\d{4})-(\d{2})|[[:alpha:]--[aeiou]]+").is_empty());
+
+        // parsing goes on from the next `)`, `|` or `]` after each error
+        let errors = crate::check(r"a\q(b|*c)d)[z-a\d]x{3,2}\p{Foo}+");
+        let spans = errors.iter().map(|e| e.span()).collect::<Vec<_>>();
+        assert_eq!(spans, [1..3, 6..7, 10..11, 12..15, 19..24, 24..31]);
+        assert!(matches!(errors[0], Error::BadEscape(_)));
+        assert!(matches!(errors[1], Error::DanglingQuantifier(_)));
+        assert!(matches!(errors[2], Error::UnopenedGroup(_)));
+        assert!(matches!(errors[3], Error::BadRange(_)));
+        assert!(matches!(errors[4], Error::BadRange(_)));
+        assert!(matches!(errors[5], Error::UnknownClass(_)));
+
+        // the error TinyRegex::new stops at is one of them
+        let pattern = r"(?P<n>a)(?P<n>b)[[:vowel:]&&b]|?(c";
+        let errors = crate::check(pattern);
+        assert_eq!(errors.len(), 4);
+        assert!(errors.contains(&TinyRegex::new(pattern).err().unwrap()));
+        assert!(matches!(errors[3], Error::UnclosedGroup(_)));
+
+        // a `-` that a malformed escape ending the pattern leaves right before EOF is an ordinary character
+        let spans = |pattern: &str| crate::check(pattern).iter().map(|e| e.span()).collect::<Vec<_>>();
+        assert_eq!(spans(r"[a-\"), [0..1, 3..4]);
+        assert_eq!(spans(r"x[a-\"), [1..2, 4..5]);
+        let errors = crate::check(r"[a-\Q");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], Error::UnclosedClass(_)));
+
+        // a malformed escape at either end of a range is reported alone, as it stands for no char
+        assert_eq!(spans(r"[a-\c"), [0..1, 3..5]);
+        assert_eq!(spans(r"[a-\p{L"), [0..1, 3..7]);
+        assert_eq!(spans(r"[a-\q]b[\q-a]"), [3..5, 8..10]);
+    }
+
+    #[test]
+    fn test_nest_limit() {
+        // long sequences and many alternatives are parsed and built without recursion
+        let literal = "ab".repeat(5_000);
+        let re = TinyRegex::new(&literal).unwrap();
+        assert_eq!(re.find(&format!("x{}y", literal)).unwrap().range(), 1..10_001);
+        let words = (0..1_000).map(|i| format!("w{}x", i)).collect::<Vec<_>>();
+        let re = TinyRegex::new(&words.join("|")).unwrap();
+        assert_eq!(re.find("a w999x b").unwrap().as_str(), "w999x");
+
+        // a long bounded repetition stays a flat sequence
+        let re = TinyRegex::new("a{2,5000}").unwrap();
+        assert_eq!(re.find("baaab").unwrap().as_str(), "aaa");
+
+        // deep nesting is an error rather than a stack overflow
+        let deep = format!("{}a{}", "(".repeat(5_000), ")".repeat(5_000));
+        let e = TinyRegex::new(&deep).err().unwrap();
+        assert!(matches!(e, Error::TooDeep(_)));
+        assert_eq!(e.span(), 250..251);
+        let e = TinyRegex::new(&format!("{}a{}", "[".repeat(300), "]".repeat(300))).err().unwrap();
+        assert!(matches!(e, Error::TooDeep(_)));
+        let e = TinyRegex::new(&format!("a{}", "{0,1}".repeat(300))).err().unwrap();
+        assert!(matches!(e, Error::TooDeep(_)));
+        assert_eq!(e.span(), 1251..1256);
+        assert_eq!(crate::check(&deep).len(), 1);
+
+        let nested = format!("{}a{}", "(".repeat(20), ")".repeat(20));
+        assert!(TinyRegex::new(&nested).is_ok());
+        assert!(TinyRegex::builder(&nested).nest_limit(20).build().is_ok());
+        assert!(matches!(TinyRegex::builder(&nested).nest_limit(19).build().err(), Some(Error::TooDeep(_))));
+        assert!(TinyRegex::builder("[[a]]").nest_limit(1).build().is_err());
+
+        // nested brackets are parsed without recursion too, so raising the limit cannot overflow the stack
+        let deep = format!("{}a{}", "[".repeat(100_000), "]".repeat(100_000));
+        let re = TinyRegex::builder(&deep).nest_limit(usize::MAX).build().unwrap();
+        assert_eq!(re.find("ba").unwrap().range(), 1..2);
+
+        // and deep groups build in time linear in their depth
+        let deep = format!("{}a{}", "(".repeat(50_000), ")".repeat(50_000));
+        let re = TinyRegex::builder(&deep).nest_limit(usize::MAX).build().unwrap();
+        assert_eq!(re.find("ba").unwrap().range(), 1..2);
+        assert!(TinyRegex::builder("a{2}{2}").nest_limit(1).build().is_err());
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let re = TinyRegex::new("[0-9]+").unwrap();
+        let numbers = re.find_iter("1, 22 and 333").map(|mat| mat.as_str()).collect::<Vec<_>>();
+        assert_eq!(numbers, ["1", "22", "333"]);
+
+        // the matches are found on demand, and a clone goes on from where it was made
+        let s = "a1 b22 c333";
+        let mut matches = re.find_iter(s);
+        assert_eq!(matches.next().unwrap().range(), 1..2);
+        let mut rest = matches.clone();
+        assert_eq!(matches.next().unwrap().as_str(), "22");
+        assert_eq!(rest.next().unwrap().as_str(), "22");
+
+        // once exhausted, the iterator stays so
+        fn fused<I: std::iter::FusedIterator>(iter: I) -> I {
+            iter
+        }
+        let mut matches = fused(re.find_iter(s));
+        assert_eq!(matches.by_ref().count(), 3);
+        assert!(matches.next().is_none());
+        assert!(matches.next().is_none());
+        assert_eq!(matches.size_hint(), (0, Some(0)));
+
+        // the bounds hold the number of matches left before every next()
+        for (pattern, s) in [("[0-9]+", "a1 b22 c333"), ("a*", "baaeé"), ("", "añb"), ("x", "abc")] {
+            let re = TinyRegex::new(pattern).unwrap();
+            let mut matches = re.find_iter(s);
+            loop {
+                let (lower, upper) = matches.size_hint();
+                let left = matches.clone().count();
+                assert!(lower <= left && upper.is_none_or(|upper| left <= upper), "{} over {:?}", pattern, s);
+                if matches.next().is_none() {
+                    break;
+                }
+            }
+        }
+
+        // one next() yields the first match alone and leaves the iterator right after it
+        let re = TinyRegex::new("b|a*c").unwrap();
+        let mut matches = re.find_iter("baacbac");
+        assert_eq!(matches.next().unwrap().range(), 0..1);
+        assert_eq!(matches.map(|mat| mat.range()).collect::<Vec<_>>(), [1..4, 4..5, 5..7]);
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_empty_matches() {
+        let ranges = |pattern: &str, s: &str| {
+            TinyRegex::new(pattern).unwrap().find_iter(s).map(|mat| mat.range()).collect::<Vec<_>>()
+        };
+
+        // a longer match wins over the empty one at the same position
+        let re = TinyRegex::new("a*").unwrap();
+        assert_eq!(re.find("aaa").unwrap().range(), 0..3);
+        assert_eq!(re.find("baaa").unwrap().range(), 0..0);
+        assert_eq!(re.find_at("baaa", 1).unwrap().range(), 1..4);
+        assert_eq!(re.find_at("baaa", 4).unwrap().range(), 4..4);
+        assert_eq!(TinyRegex::new("(b|)").unwrap().find("bb").unwrap().range(), 0..1);
+
+        // an empty match right after a match is skipped, and the search goes on a char later
+        assert_eq!(ranges("a*", "aaa"), [0..3]);
+        assert_eq!(ranges("a*", "baaa"), [0..0, 1..4]);
+        assert_eq!(ranges("a*", "bab"), [0..0, 1..2, 3..3]);
+        assert_eq!(ranges("a*", "baac"), [0..0, 1..3, 4..4]);
+        assert_eq!(ranges("a*", ""), [0..0]);
+        assert_eq!(ranges("(b|)", "xbb"), [0..0, 1..2, 2..3]);
+        assert_eq!(ranges("", "ab"), [0..0, 1..1, 2..2]);
+        assert_eq!(ranges("x*", "日本"), [0..0, 3..3, 6..6]);
+        assert_eq!(ranges(r"\b", "ab cd"), [0..0, 2..2, 3..3, 5..5]);
+        assert_eq!(ranges(r"(?m)^", "a\nb\n"), [0..0, 2..2, 4..4]);
+        assert_eq!(ranges(r"(?m)$", "a\nb"), [1..1, 3..3]);
+        assert_eq!(ranges("a|", "aba"), [0..1, 2..3]);
+        assert_eq!(ranges("a", ""), []);
+
+        // the upper bound covers every match left, and find_all agrees with find_iter
+        let re = TinyRegex::new("").unwrap();
+        let mut matches = re.find_all("abc");
+        assert_eq!(matches.size_hint(), (0, Some(4)));
+        matches.next();
+        assert_eq!(matches.size_hint(), (0, Some(3)));
+        assert_eq!(matches.count(), 3);
     }
 
 }
\ No newline at end of file
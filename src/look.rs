@@ -0,0 +1,136 @@
+use crate::unicode::*;
+
+/**
+ * zero-width assertions that hold between two chars rather than matching one
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Look {
+    StartText,
+    EndText,
+    // `^` and `$` in multi-line mode
+    StartLine,
+    EndLine,
+    // `^` and `$` in multi-line mode where `\r`, `\n` and `\r\n` end a line
+    StartLineCrlf,
+    EndLineCrlf,
+    WordBoundary,
+    NotWordBoundary,
+    // `\b` and `\B` without Unicode, where only `[0-9A-Za-z_]` are word chars
+    WordBoundaryAscii,
+    NotWordBoundaryAscii
+}
+
+/**
+ * what an assertion can see on either side of a position in the input
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Adjacent {
+    // the start or the end of the input
+    Edge,
+    LineFeed,
+    CarriageReturn,
+    // the chars matching `[0-9A-Za-z_]`
+    AsciiWord,
+    // the other chars matching `\w`
+    UnicodeWord,
+    // any char no assertion in the pattern tells apart
+    Char
+}
+
+/**
+ * @return: the ranges of chars of the kind, which are none for the edges and the undistinguished chars
+ */
+pub fn adjacent_table(adjacent: Adjacent) -> &'static [(char, char)] {
+    match adjacent {
+        Adjacent::Edge | Adjacent::Char => &[],
+        Adjacent::LineFeed => &[('\n', '\n')],
+        Adjacent::CarriageReturn => &[('\r', '\r')],
+        Adjacent::AsciiWord => POSIX_WORD,
+        // PERL_WORD starts with the ranges of POSIX_WORD
+        Adjacent::UnicodeWord => &PERL_WORD[POSIX_WORD.len()..]
+    }
+}
+
+fn is_word(adjacent: Adjacent) -> bool {
+    matches!(adjacent, Adjacent::AsciiWord | Adjacent::UnicodeWord)
+}
+
+/**
+ * @param c: the char next to a position, or None at the edges of the input
+ * @param distinguished: the kinds of chars the assertions tell apart
+ */
+pub fn adjacent(c: Option<char>, distinguished: &[Adjacent]) -> Adjacent {
+    let adjacent = match c {
+        None => Adjacent::Edge,
+        Some('\n') => Adjacent::LineFeed,
+        Some('\r') => Adjacent::CarriageReturn,
+        Some(c) if contains(POSIX_WORD, c) => Adjacent::AsciiWord,
+        Some(c) if contains(PERL_WORD, c) => Adjacent::UnicodeWord,
+        Some(_) => Adjacent::Char
+    };
+
+    if distinguished.contains(&adjacent) {
+        adjacent
+    }
+    else {
+        Adjacent::Char
+    }
+}
+
+impl Look {
+    /**
+     * @param prev: what precedes the position
+     * @param next: what follows the position
+     * @return: true iff. the assertion holds at the position
+     */
+    pub fn is_satisfied(&self, prev: Adjacent, next: Adjacent) -> bool {
+        match self {
+            Look::StartText => prev == Adjacent::Edge,
+            Look::EndText => next == Adjacent::Edge,
+            Look::StartLine => prev == Adjacent::Edge || prev == Adjacent::LineFeed,
+            Look::EndLine => next == Adjacent::Edge || next == Adjacent::LineFeed,
+            // never between `\r` and `\n`
+            Look::StartLineCrlf => {
+                matches!(prev, Adjacent::Edge | Adjacent::LineFeed) || (prev == Adjacent::CarriageReturn && next != Adjacent::LineFeed)
+            }
+            Look::EndLineCrlf => {
+                matches!(next, Adjacent::Edge | Adjacent::CarriageReturn) || (next == Adjacent::LineFeed && prev != Adjacent::CarriageReturn)
+            }
+            Look::WordBoundary => is_word(prev) != is_word(next),
+            Look::NotWordBoundary => is_word(prev) == is_word(next),
+            Look::WordBoundaryAscii => (prev == Adjacent::AsciiWord) != (next == Adjacent::AsciiWord),
+            Look::NotWordBoundaryAscii => (prev == Adjacent::AsciiWord) == (next == Adjacent::AsciiWord)
+        }
+    }
+
+    /**
+     * @return: the kinds of chars the assertion tells apart from the others
+     */
+    pub fn adjacents(&self) -> &'static [Adjacent] {
+        match self {
+            Look::StartText | Look::EndText => &[Adjacent::Edge],
+            Look::StartLine | Look::EndLine => &[Adjacent::Edge, Adjacent::LineFeed],
+            Look::StartLineCrlf | Look::EndLineCrlf => &[Adjacent::Edge, Adjacent::LineFeed, Adjacent::CarriageReturn],
+            // the edges of the input count as non-word chars
+            Look::WordBoundary | Look::NotWordBoundary => &[Adjacent::AsciiWord, Adjacent::UnicodeWord],
+            Look::WordBoundaryAscii | Look::NotWordBoundaryAscii => &[Adjacent::AsciiWord]
+        }
+    }
+}
+
+impl std::fmt::Display for Look {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Look::StartText => write!(f, "\\A"),
+            Look::EndText => write!(f, "\\z"),
+            Look::StartLine => write!(f, "(?m:^)"),
+            Look::EndLine => write!(f, "(?m:$)"),
+            Look::StartLineCrlf => write!(f, "(?mR:^)"),
+            Look::EndLineCrlf => write!(f, "(?mR:$)"),
+            Look::WordBoundary => write!(f, "\\b"),
+            Look::NotWordBoundary => write!(f, "\\B"),
+            Look::WordBoundaryAscii => write!(f, "(?-u:\\b)"),
+            Look::NotWordBoundaryAscii => write!(f, "(?-u:\\B)")
+        }
+    }
+}
@@ -1,5 +1,3 @@
-#[allow(dead_code)]
-
 use std::io::{self, Read};
 use tiny_regex::TinyRegex;
 
@@ -12,15 +10,18 @@ enumerate all substrings matching the given regex by searching stdin until EOF
 Usage: {} [regex_str]
 [regex_str]: regex string to search
 
-arguments after the first one are ignored", args.get(0).unwrap());
+arguments after the first one are ignored", args.first().unwrap());
 
     let regex_str = args.get(1).ok_or_else(|| {eprintln!("{}", usage); "regex string is not provided"})?;
-    let re = TinyRegex::new(regex_str).unwrap();
+    let re = TinyRegex::new(regex_str).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
 
     let mut buffer = String::new();
     io::stdin().read_to_string(&mut buffer)?;
 
-    for mat in re.find_all(&buffer) {
+    for mat in re.find_iter(&buffer) {
         println!("{}", mat.as_str());
     }
 
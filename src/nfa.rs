@@ -1,37 +1,47 @@
-#[allow(dead_code)]
-
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::parse::*;
+use crate::look::*;
+use crate::class::*;
 
 
 const DEAD_STATE: usize = usize::MAX;
 
+#[derive(Clone)]
 pub struct State {
-    pub transitions: HashMap<char, usize>,
+    // sorted, non-overlapping ranges of chars and the states they lead to
+    pub transitions: Vec<(char, char, usize)>,
     pub epsilon_transitions: Vec<usize>,
-    pub default_transition: usize,
+    pub look_transitions: Vec<(Look, usize)>,
+    // the capture slot recording the position the state is passed at, 2i at the start and 2i+1 at the end of group i
+    pub slot: Option<usize>,
     pub state_num: usize
 }
 
+#[derive(Clone)]
 pub struct NFA {
     states: HashMap<usize, State>,
     start: usize,
-    accept: usize
+    accept: usize,
+    has_look: bool,
+    // the kinds of chars the assertions in the NFA tell apart
+    adjacents: Vec<Adjacent>
 }
 
 #[allow(dead_code)]
 impl State {
     pub fn new(state_num: usize) -> State {
         State {
-            transitions: HashMap::new(),
+            transitions: Vec::new(),
             epsilon_transitions: Vec::new(),
-            default_transition: DEAD_STATE,
+            look_transitions: Vec::new(),
+            slot: None,
             state_num
         }
     }
 
-    pub fn add_transition(&mut self, c: char, state_num: usize) {
-        self.transitions.insert(c, state_num);
+    pub fn add_transition(&mut self, start: char, end: char, state_num: usize) {
+        let i = self.transitions.partition_point(|&(s, _, _)| s < start);
+        self.transitions.insert(i, (start, end, state_num));
     }
 
     pub fn add_epsilon_transition(&mut self, state_num: usize) {
@@ -39,12 +49,24 @@ impl State {
         self.epsilon_transitions.sort();
     }
 
-    pub fn get_transition(&self, c: char) -> Option<&usize> {
-        self.transitions.get(&c)
+    pub fn add_look_transition(&mut self, look: Look, state_num: usize) {
+        self.look_transitions.push((look, state_num));
+    }
+
+    pub fn get_transition(&self, c: char) -> Option<usize> {
+        let i = self.transitions.partition_point(|&(_, end, _)| end < c);
+        self.transitions.get(i).filter(|&&(start, _, _)| start <= c).map(|&(_, _, state_num)| state_num)
+    }
+
+    pub fn set_slot(&mut self, slot: usize) {
+        self.slot = Some(slot);
     }
 
-    pub fn set_default_transition(&mut self, state_num: usize) {
-        self.default_transition = state_num;
+    /**
+     * @return: the state reached by consuming c, which may be the dead state
+     */
+    pub fn next_state(&self, c: char) -> usize {
+        self.get_transition(c).unwrap_or(DEAD_STATE)
     }
 
     pub fn get_state_num(&self) -> usize {
@@ -58,38 +80,197 @@ impl NFA {
         NFA {
             states: HashMap::new(),
             start: 0,
-            accept: 0
+            accept: 0,
+            has_look: false,
+            adjacents: vec![Adjacent::Char]
         }
     }
 
     pub fn epsilon_closure(&self, state_num: usize) -> Vec<usize> {
+        self.closure(&[state_num], |_| false)
+    }
+
+    /**
+     * @brief the epsilon closure which also passes through the assertions holding between prev and next
+     */
+    pub fn look_closure(&self, state_nums: &[usize], prev: Adjacent, next: Adjacent) -> Vec<usize> {
+        self.closure(state_nums, |look| look.is_satisfied(prev, next))
+    }
+
+    fn closure(&self, state_nums: &[usize], is_satisfied: impl Fn(Look) -> bool) -> Vec<usize> {
         let mut closure = Vec::new();
-        let mut visited = Vec::<usize>::new();
-        let mut stack = Vec::new();
-        stack.push(state_num);
+        let mut visited = HashSet::new();
+        let mut stack = state_nums.to_vec();
 
         while let Some(state_num) = stack.pop() {
-            if visited.contains(&state_num) {
+            if !visited.insert(state_num) {
                 continue;
             }
 
-            visited.push(state_num);
             closure.push(state_num);
 
             let state = self.get_state(state_num).unwrap();
             state.epsilon_transitions.iter().for_each(|&next_state_num| {
                 stack.push(next_state_num);
             });
+            state.look_transitions.iter().filter(|(look, _)| is_satisfied(*look)).for_each(|&(_, next_state_num)| {
+                stack.push(next_state_num);
+            });
         }
 
         closure.sort();
         closure
     }
 
+    /**
+     * @param state_nums: the states to move from, closed under epsilon transitions
+     * @param c: the char to consume
+     * @return: the epsilon closure of the states reached by consuming c
+     */
+    pub fn step(&self, state_nums: &[usize], c: char) -> Vec<usize> {
+        let mut next_state_nums = Vec::new();
+
+        for &state_num in state_nums.iter() {
+            let state = self.get_state(state_num).unwrap();
+            next_state_nums.extend(self.epsilon_closure(state.next_state(c)));
+        }
+
+        next_state_nums.sort();
+        next_state_nums.dedup();
+        next_state_nums
+    }
+
+    /**
+     * @param state_nums: the states to move from, closed under epsilon transitions
+     * @param prev: the kind of char consumed right before reaching the states
+     * @param next: the kind of c
+     * @param c: the char to consume
+     * @return: the epsilon closure of the states reached by consuming c once the assertions are resolved
+     */
+    pub fn next_states(&self, state_nums: &[usize], prev: Adjacent, next: Adjacent, c: char) -> Vec<usize> {
+        if self.has_look {
+            self.step(&self.look_closure(state_nums, prev, next), c)
+        }
+        else {
+            self.step(state_nums, c)
+        }
+    }
+
+    /**
+     * @return: the kinds of adjacent chars that make a difference to the assertions in the NFA
+     */
+    pub fn adjacents(&self) -> &[Adjacent] {
+        &self.adjacents
+    }
+
+    /**
+     * @brief classifies the char next to a position; every position looks alike without assertions
+     */
+    pub fn adjacent(&self, c: Option<char>) -> Adjacent {
+        adjacent(c, &self.adjacents)
+    }
+
+    /**
+     * @return: the sorted chars starting the runs of chars that every state, every state behind their assertions
+     *          and the assertions themselves treat alike, always including '\0'
+     */
+    pub fn boundaries(&self, state_nums: &[usize]) -> Vec<char> {
+        let transitions = self.closure(state_nums, |_| true).into_iter()
+            .flat_map(|state_num| self.get_state(state_num).unwrap().transitions.iter().map(|&(start, end, _)| (start, end)));
+        let adjacents = self.adjacents.iter().flat_map(|&adjacent| adjacent_table(adjacent).iter().cloned());
+
+        let mut chars = vec!['\0'];
+        for (start, end) in transitions.chain(adjacents) {
+            chars.push(start);
+            chars.extend(next_char(end));
+        }
+
+        chars.sort();
+        chars.dedup();
+        chars
+    }
+
+    /**
+     * @brief runs the NFA over s[start..end] as a Pike VM, keeping the threads in the order of priority
+     *        so that the greedy quantifiers and the left alternatives win
+     * @param slots: the number of capture slots, 2 per group including the whole match
+     * @return: the positions of the slots along the preferred path matching exactly s[start..end]
+     */
+    pub fn captures(&self, s: &str, start: usize, end: usize, slots: usize) -> Option<Vec<Option<usize>>> {
+        let mut initial = vec![None; slots];
+        initial[0] = Some(start);
+
+        let mut threads = Vec::new();
+        self.add_thread(&mut threads, &mut HashSet::new(), s, start, self.start, initial);
+
+        for (i, c) in s[start..end].char_indices() {
+            let next = start + i + c.len_utf8();
+            let mut next_threads = Vec::new();
+            let mut visited = HashSet::new();
+
+            for (state_num, captures) in threads {
+                let next_state_num = self.get_state(state_num).unwrap().next_state(c);
+                if next_state_num != DEAD_STATE {
+                    self.add_thread(&mut next_threads, &mut visited, s, next, next_state_num, captures);
+                }
+            }
+
+            threads = next_threads;
+        }
+
+        threads.into_iter().find(|&(state_num, _)| state_num == self.accept).map(|(_, mut captures)| {
+            captures[1] = Some(end);
+            captures
+        })
+    }
+
+    /**
+     * @brief appends the thread and the threads its epsilon closure reaches at the position pos,
+     *        unless a thread of higher priority has already visited the state
+     */
+    fn add_thread(&self, threads: &mut Vec<(usize, Vec<Option<usize>>)>, visited: &mut HashSet<usize>, s: &str, pos: usize, state_num: usize, captures: Vec<Option<usize>>) {
+        let prev = self.adjacent(s[..pos].chars().next_back());
+        let next = self.adjacent(s[pos..].chars().next());
+        let mut stack = vec![(state_num, captures)];
+
+        while let Some((state_num, mut captures)) = stack.pop() {
+            if !visited.insert(state_num) {
+                continue;
+            }
+
+            let state = self.get_state(state_num).unwrap();
+            if let Some(slot) = state.slot {
+                captures[slot] = Some(pos);
+            }
+
+            // pushed in reverse so that the first transition is followed first
+            state.look_transitions.iter().rev().filter(|(look, _)| look.is_satisfied(prev, next)).for_each(|&(_, next_state_num)| {
+                stack.push((next_state_num, captures.clone()));
+            });
+            state.epsilon_transitions.iter().rev().for_each(|&next_state_num| {
+                stack.push((next_state_num, captures.clone()));
+            });
+
+            threads.push((state_num, captures));
+        }
+    }
+
+
     pub fn add_epsilon_transition(&mut self, state_num: usize, next_state_num: usize) {
         self.states.get_mut(&state_num).unwrap().add_epsilon_transition(next_state_num);
     }
 
+    pub fn add_look_transition(&mut self, state_num: usize, look: Look, next_state_num: usize) {
+        self.states.get_mut(&state_num).unwrap().add_look_transition(look, next_state_num);
+        self.has_look = true;
+
+        for &adjacent in look.adjacents() {
+            if !self.adjacents.contains(&adjacent) {
+                self.adjacents.push(adjacent);
+            }
+        }
+    }
+
     fn add_state(&mut self, state: State) {
         self.states.insert(state.state_num, state);
     }
@@ -126,116 +307,170 @@ impl NFA {
 
 pub fn build_nfa(root: Node) -> NFA {
     let mut nfa = NFA::new();
-    let mut state_num = nfa.get_start();
     let dead_state = State::new(DEAD_STATE);
     nfa.add_state(dead_state);
-    state_num = build_nfa_rec(root, &mut nfa, state_num);
-    nfa.set_accept(state_num);
+
+    // the states of a node are numbered consecutively from its start to its accept, so they are all
+    // known before the transitions between them are added
+    let sizes = nfa_sizes(&root);
+    let start_num = nfa.get_start();
+    let accept_num = start_num + sizes.last().unwrap().0 - 1;
+    for state_num in start_num..=accept_num {
+        nfa.add_state(State::new(state_num));
+    }
+    nfa.set_accept(accept_num);
+
+    // the nodes left to build with their indices in sizes and their start and accept state numbers,
+    // kept on an explicit stack so that a deep AST does not overflow the call stack
+    let mut stack = vec![(&root, sizes.len() - 1, start_num, accept_num)];
+    while let Some((node, index, start_num, accept_num)) = stack.pop() {
+        build_node(node, index, &mut nfa, start_num, accept_num, &sizes, &mut stack);
+    }
+
+    // the AST is taken apart on an explicit stack as well, as dropping a deep one would recurse
+    let mut nodes = vec![root];
+    while let Some(node) = nodes.pop() {
+        match node {
+            Node::Concat(children) | Node::Union(children) => nodes.extend(children),
+            Node::Repeat(child) | Node::OneOrMore(child) | Node::Optional(child) | Node::AtMost(_, child) | Node::Group(_, child) => nodes.push(*child),
+            Node::Empty | Node::Char(_) | Node::Class(_) | Node::Look(_) => {}
+        }
+    }
+
     nfa
 }
 
 
 /**
- * @param root: The root of the AST
- * @param nfa: The NFA to build
- * @param state_num: The start state number
- * @return: the accept state number
+ * @return: the indices of the children of the node at index in sizes, whose subtrees end one after another
+ *          right before the node
  */
+fn child_indices(node: &Node, index: usize, sizes: &[(usize, usize)]) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(node.children().len());
+    let mut end = index;
 
-fn build_nfa_rec(root: Node, nfa: &mut NFA, state_num: usize) -> usize {
-    match root {
+    for _ in node.children() {
+        indices.push(end - 1);
+        end -= sizes[end - 1].1;
+    }
+
+    indices.reverse();
+    indices
+}
+
+
+/**
+ * @brief adds the transitions of the node, pushing its children to build later
+ * @param node: the node to build
+ * @param index: the index of the node in sizes
+ * @param nfa: the NFA to build, which already has the states of the node
+ * @param start_num: the start state number
+ * @param accept_num: the accept state number
+ * @param sizes: the number of states of each node and the number of nodes in its subtree, in post-order
+ * @param stack: the nodes left to build with their indices and their start and accept state numbers
+ */
+fn build_node<'a>(node: &'a Node, index: usize, nfa: &mut NFA, start_num: usize, accept_num: usize, sizes: &[(usize, usize)], stack: &mut Vec<(&'a Node, usize, usize, usize)>) {
+    // the only child right before the node in sizes, with its states right inside the start and the accept
+    let only_child = || (index - 1, start_num + 1, accept_num - 1);
+
+    match node {
+        Node::Empty => {
+            nfa.add_epsilon_transition(start_num, accept_num);
+        }
         Node::Char(c) => {
-            let start_state_num = state_num;
-            let mut start = State::new(start_state_num);
-            let accept_state_num = start_state_num + 1;
-            let accept = State::new(accept_state_num);
-            start.add_transition(c, accept_state_num);
-            nfa.add_state(start);
-            nfa.add_state(accept);
-            accept_state_num
+            nfa.get_state_mut(start_num).unwrap().add_transition(*c, *c, accept_num);
         }
-        Node::Concat((child1, child2)) => {
-            let new_start_num = state_num;
-            let new_start = State::new(state_num);
-            
-            let child1_start_num = new_start_num + 1;
-            let child1_accept_num = build_nfa_rec(*child1, nfa, child1_start_num);
-            
-            let child2_start_num = child1_accept_num + 1;
-            let child2_accept_num = build_nfa_rec(*child2, nfa, child2_start_num);
-            
-            let new_accept_num = child2_accept_num + 1;
-            let new_accept = State::new(new_accept_num);
-
-            nfa.add_state(new_start);
-            nfa.add_state(new_accept);
-
-            nfa.add_epsilon_transition(new_start_num, child1_start_num);
-            nfa.add_epsilon_transition(child1_accept_num, child2_start_num);
-            nfa.add_epsilon_transition(child2_accept_num, new_accept_num);
-                        
-            new_accept_num
+        Node::Concat(children) => {
+            let mut prev_accept_num = start_num;
+            let mut child_start_num = start_num + 1;
+
+            for (child, child_index) in children.iter().zip(child_indices(node, index, sizes)) {
+                let child_accept_num = child_start_num + sizes[child_index].0 - 1;
+                nfa.add_epsilon_transition(prev_accept_num, child_start_num);
+                stack.push((child, child_index, child_start_num, child_accept_num));
+
+                prev_accept_num = child_accept_num;
+                child_start_num = child_accept_num + 1;
+            }
+            nfa.add_epsilon_transition(prev_accept_num, accept_num);
         }
-        Node::Union((child1, child2)) => {
-            let new_start_num = state_num;
-            let new_start = State::new(state_num);
-            
-            let child1_start_num = new_start_num + 1;
-            let child1_accept_num = build_nfa_rec(*child1, nfa, child1_start_num);
-            
-            let child2_start_num = child1_accept_num + 1;
-            let child2_accept_num = build_nfa_rec(*child2, nfa, child2_start_num);
-            
-            let new_accept_num = child2_accept_num + 1;
-            let new_accept = State::new(new_accept_num);
-            
-            nfa.add_state(new_start);
-            nfa.add_epsilon_transition(new_start_num, child1_start_num);
-            nfa.add_epsilon_transition(new_start_num, child2_start_num);
-
-            nfa.add_state(new_accept);
-            nfa.add_epsilon_transition(child1_accept_num, new_accept_num);
-            nfa.add_epsilon_transition(child2_accept_num, new_accept_num);
-            
-            new_accept_num
+        Node::Union(children) => {
+            // the epsilon transitions are sorted, so the earlier alternatives numbered lower are preferred
+            let mut child_start_num = start_num + 1;
+
+            for (child, child_index) in children.iter().zip(child_indices(node, index, sizes)) {
+                let child_accept_num = child_start_num + sizes[child_index].0 - 1;
+                nfa.add_epsilon_transition(start_num, child_start_num);
+                nfa.add_epsilon_transition(child_accept_num, accept_num);
+                stack.push((child, child_index, child_start_num, child_accept_num));
+
+                child_start_num = child_accept_num + 1;
+            }
         }
         Node::Repeat(child) => {
-            let new_start_num = state_num;
-            let new_start = State::new(state_num);
-            
-            let child_start_num = new_start_num + 1;
-            let child_accept_num = build_nfa_rec(*child, nfa, child_start_num);
+            let (child_index, child_start_num, child_accept_num) = only_child();
 
-            let new_accept_num = child_accept_num + 1;
-            let new_accept = State::new(new_accept_num);
+            nfa.add_epsilon_transition(start_num, child_start_num);
+            nfa.add_epsilon_transition(child_accept_num, child_start_num);
+            nfa.add_epsilon_transition(child_accept_num, start_num);
+            nfa.add_epsilon_transition(start_num, accept_num);
 
-            nfa.add_state(new_start);
-            nfa.add_state(new_accept);
+            stack.push((child, child_index, child_start_num, child_accept_num));
+        }
+        Node::OneOrMore(child) => {
+            let (child_index, child_start_num, child_accept_num) = only_child();
 
-            nfa.add_epsilon_transition(new_start_num, child_start_num);
+            nfa.add_epsilon_transition(start_num, child_start_num);
             nfa.add_epsilon_transition(child_accept_num, child_start_num);
-            nfa.add_epsilon_transition(child_accept_num, new_start_num);
-            nfa.add_epsilon_transition(new_start_num, new_accept_num);
+            nfa.add_epsilon_transition(child_accept_num, accept_num);
 
-            new_accept_num
+            stack.push((child, child_index, child_start_num, child_accept_num));
         }
-        Node::NegChar(set) => {
-            let new_start_num = state_num;
-            let mut new_start = State::new(state_num);
+        Node::Optional(child) => {
+            let (child_index, child_start_num, child_accept_num) = only_child();
 
-            let new_accept_num = new_start_num + 1;
-            let new_accept = State::new(new_accept_num);
+            nfa.add_epsilon_transition(start_num, child_start_num);
+            nfa.add_epsilon_transition(start_num, accept_num);
+            nfa.add_epsilon_transition(child_accept_num, accept_num);
 
-            for c in set {
-                new_start.add_transition(c, DEAD_STATE);
+            stack.push((child, child_index, child_start_num, child_accept_num));
+        }
+        Node::AtMost(n, child) => {
+            // the copies follow one another, and the end of each one but the last can skip the rest
+            let child_index = index - 1;
+            let child_size = sizes[child_index].0;
+            let mut prev_accept_num = start_num;
+
+            for i in 0..*n {
+                let child_start_num = start_num + 1 + i * child_size;
+                let child_accept_num = child_start_num + child_size - 1;
+                nfa.add_epsilon_transition(prev_accept_num, child_start_num);
+                nfa.add_epsilon_transition(prev_accept_num, accept_num);
+                stack.push((child, child_index, child_start_num, child_accept_num));
+
+                prev_accept_num = child_accept_num;
             }
+            nfa.add_epsilon_transition(prev_accept_num, accept_num);
+        }
+        Node::Look(look) => {
+            nfa.add_look_transition(start_num, *look, accept_num);
+        }
+        Node::Group(group, child) => {
+            let (child_index, child_start_num, child_accept_num) = only_child();
 
-            new_start.set_default_transition(new_accept_num);
+            nfa.get_state_mut(start_num).unwrap().set_slot(2 * group);
+            nfa.get_state_mut(accept_num).unwrap().set_slot(2 * group + 1);
 
-            nfa.add_state(new_start);
-            nfa.add_state(new_accept);
+            nfa.add_epsilon_transition(start_num, child_start_num);
+            nfa.add_epsilon_transition(child_accept_num, accept_num);
 
-            new_accept_num
+            stack.push((child, child_index, child_start_num, child_accept_num));
+        }
+        Node::Class(class) => {
+            let start = nfa.get_state_mut(start_num).unwrap();
+            for &(start_char, end_char) in class.ranges() {
+                start.add_transition(start_char, end_char, accept_num);
+            }
         }
     }
 }
@@ -244,29 +479,29 @@ fn build_nfa_rec(root: Node, nfa: &mut NFA, state_num: usize) -> usize {
 pub fn print_nfa(nfa: &NFA) {
     println!("digraph PARSE {{");
     println!("\tnode [shape=circle]");
-    println!("");
+    println!();
 
     nfa.get_states().iter().for_each(|(&_state_num, state)| {
         let state_num = state.get_state_num();
         let transitions = state.transitions.iter();
         let epsilon_transitions = state.epsilon_transitions.iter();
-        let default_transition = state.default_transition;
+        let look_transitions = state.look_transitions.iter();
 
         println!("\tn{} [label=\"n{}\"]", state_num, state_num);
 
-        transitions.for_each(|(&c, next_state_num)| {
-            println!("\tn{} -> n{} [label=\"{}\"]", state_num, next_state_num, c);
+        transitions.for_each(|&(start, end, next_state_num)| {
+            println!("\tn{} -> n{} [label=\"{}\"]", state_num, next_state_num, Class::new([(start, end)]).to_string().escape_default());
         });
 
         epsilon_transitions.for_each(|&next_state_num| {
             println!("\tn{} -> n{} [label=\"ε\"]", state_num, next_state_num);
         });
 
-        if default_transition != DEAD_STATE {
-            println!("\tn{} -> n{} [label=\"default\"]", state_num, default_transition);
-        }
+        look_transitions.for_each(|(look, next_state_num)| {
+            println!("\tn{} -> n{} [label=\"{}\"]", state_num, next_state_num, look);
+        });
     });
 
-    println!("");
+    println!();
     println!("}}");
 }
\ No newline at end of file
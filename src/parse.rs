@@ -1,327 +1,961 @@
-use std::collections::HashSet;
-#[allow(dead_code)]
-
-use std::collections::VecDeque;
+use std::ops::Range;
 use crate::token::*;
+use crate::look::*;
+use crate::unicode::*;
+use crate::class::*;
+use crate::error::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Node {
+    // the empty string
+    Empty,
     Char(char),
-    Concat((Box<Node>, Box<Node>)),
-    Union((Box<Node>, Box<Node>)),
+    // the children in order, at least two of them, kept flat so that a long sequence or many alternatives
+    // do not deepen the tree
+    Concat(Vec<Node>),
+    Union(Vec<Node>),
     Repeat(Box<Node>),
-    NegChar(HashSet<char>)
+    OneOrMore(Box<Node>),
+    Optional(Box<Node>),
+    // up to the number of copies of the node, each matching only after the previous one as in `(a(a)?)?`,
+    // which is kept as a single node so that a long counted repetition does not deepen the tree
+    AtMost(usize, Box<Node>),
+    Look(Look),
+    // a capture group with its index, counted from 1 in the order of the opening parentheses
+    Group(usize, Box<Node>),
+    // any char of the class
+    Class(Class)
 }
 
 
-pub fn parse(tokens: &mut VecDeque<Token>) -> Result<Node, String> {
-    match expr(tokens) {
-        Ok(root) => Ok(*root),
-        Err(e) => Err(format!("Parse Error: {}", e))
+pub const DEFAULT_SIZE_LIMIT: usize = 1_000_000;
+pub const DEFAULT_NEST_LIMIT: usize = 250;
+pub const DEFAULT_DFA_SIZE_LIMIT: usize = 100_000;
+
+pub struct Config {
+    // the maximum number of NFA states the pattern may compile to
+    pub size_limit: usize,
+    // the maximum depth groups, brackets and counted repetitions may nest to
+    pub nest_limit: usize,
+    // the maximum number of DFA states the pattern may compile to, which can grow exponentially in the NFA states
+    pub dfa_size_limit: usize,
+    // whether `\d`, `\w` and `\s` match Unicode chars or ASCII chars only
+    pub unicode: bool,
+    // whether `.` matches `\n` without the `s` flag
+    pub dot_matches_new_line: bool,
+    // whether `\r\n` ends a line for `.`, `^` and `$` without the `R` flag
+    pub crlf: bool
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            size_limit: DEFAULT_SIZE_LIMIT,
+            nest_limit: DEFAULT_NEST_LIMIT,
+            dfa_size_limit: DEFAULT_DFA_SIZE_LIMIT,
+            unicode: true,
+            dot_matches_new_line: false,
+            crlf: false
+        }
     }
 }
 
 
+// the flags set by `(?flags)` and `(?flags:...)`, scoped by the enclosing group
+#[derive(Clone, Copy)]
+struct Flags {
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_matches_new_line: bool,
+    crlf: bool
+}
+
+impl Flags {
+    fn new(config: &Config) -> Flags {
+        Flags {
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: config.dot_matches_new_line,
+            crlf: config.crlf
+        }
+    }
+
+    fn set(&mut self, flags: &[(Flag, bool)]) {
+        for &(flag, on) in flags {
+            match flag {
+                Flag::CaseInsensitive => self.case_insensitive = on,
+                Flag::MultiLine => self.multi_line = on,
+                Flag::DotMatchesNewLine => self.dot_matches_new_line = on,
+                Flag::Crlf => self.crlf = on,
+                // verbose mode is handled by the tokenizer
+                Flag::Verbose => {}
+            }
+        }
+    }
+}
+
+struct ParseState {
+    // the names of the capture groups found so far, None for the unnamed ones
+    groups: Vec<Option<String>>,
+    flags: Flags,
+    // the number of groups and brackets enclosing the token parsed now
+    depth: usize,
+    // the errors found so far in check mode, None outside it, where the first error stops parsing
+    errors: Option<Vec<Error>>
+}
+
+
+/**
+ * @return: the root of the AST and the names of its capture groups, None for the unnamed ones
+ */
+pub fn parse(tokens: &mut Tokens, config: &Config) -> Result<(Node, Vec<Option<String>>), Error> {
+    let mut state = ParseState {
+        groups: Vec::new(),
+        flags: Flags::new(config),
+        depth: 0,
+        errors: None
+    };
+
+    let root = expr(tokens, config, &mut state)?;
+    Ok((*root, state.groups))
+}
+
+
+/**
+ * @return: every error in the tokens, as parsing goes on after each one from the next `)`, `|` or `]`
+ */
+pub fn check(tokens: &mut Tokens, config: &Config) -> Vec<Error> {
+    let mut state = ParseState {
+        groups: Vec::new(),
+        flags: Flags::new(config),
+        depth: 0,
+        errors: Some(Vec::new())
+    };
+
+    let result = expr(tokens, config, &mut state);
+    let mut errors = state.errors.unwrap_or_default();
+    // the errors parsing cannot go on after, which are bugs of the parser
+    if let Err(e) = result {
+        errors.push(e);
+    }
+    errors
+}
+
+
+impl Node {
+    pub fn children(&self) -> &[Node] {
+        match self {
+            Node::Empty | Node::Char(_) | Node::Class(_) | Node::Look(_) => &[],
+            Node::Concat(children) | Node::Union(children) => children,
+            Node::Repeat(child) | Node::OneOrMore(child) | Node::Optional(child) | Node::AtMost(_, child) | Node::Group(_, child) => std::slice::from_ref(child)
+        }
+    }
+}
+
+
+/**
+ * @param root: The root of the AST
+ * @return: the number of NFA states build_nfa generates for the AST
+ */
+pub fn nfa_size(root: &Node) -> usize {
+    nfa_sizes(root).last().unwrap().0
+}
+
+/**
+ * @param root: The root of the AST
+ * @return: the number of NFA states build_nfa generates for each node and the number of nodes in its subtree,
+ *          in post-order so that the root comes last and the subtree of each node ends right before it
+ */
+pub fn nfa_sizes(root: &Node) -> Vec<(usize, usize)> {
+    let mut sizes: Vec<(usize, usize)> = Vec::new();
+    // the nodes with whether their children are sized already, the first child popped first
+    let mut stack = vec![(root, false)];
+
+    while let Some((node, sized)) = stack.pop() {
+        if !sized {
+            stack.push((node, true));
+            stack.extend(node.children().iter().rev().map(|child| (child, false)));
+            continue;
+        }
+
+        let mut states = 0;
+        let mut nodes = 1;
+        for _ in node.children() {
+            let (child_states, child_nodes) = sizes[sizes.len() - nodes];
+            states += child_states;
+            nodes += child_nodes;
+        }
+
+        // every copy of a node has a start and an accept state of its own
+        let copies = if let Node::AtMost(n, _) = node { *n } else { 1 };
+        sizes.push((2 + copies * states, nodes));
+    }
+
+    sizes
+}
+
+
+fn empty() -> Box<Node> {
+    Box::new(Node::Empty)
+}
+
 fn character(c: char) -> Box<Node> {
     Box::new(Node::Char(c))
 }
 
-fn neg_character(c: char) -> HashSet<char> {
-    let mut set = HashSet::new();
-    set.insert(c);
-    set
+fn class(class: Class) -> Box<Node> {
+    Box::new(Node::Class(class))
 }
 
-fn concat(node1: Node, node2: Node) -> Box<Node> {
-    Box::new(Node::Concat((Box::new(node1), Box::new(node2))))
+// the empty string for no nodes and the node itself for a single one
+fn concat(mut nodes: Vec<Node>) -> Box<Node> {
+    match nodes.len() {
+        0 => empty(),
+        1 => Box::new(nodes.pop().unwrap()),
+        _ => Box::new(Node::Concat(nodes))
+    }
 }
 
-fn union(node1: Node, node2: Node) -> Box<Node> {
-    Box::new(Node::Union((Box::new(node1), Box::new(node2))))
+// the node itself for a single alternative
+fn union(mut nodes: Vec<Node>) -> Box<Node> {
+    match nodes.len() {
+        1 => Box::new(nodes.pop().unwrap()),
+        _ => Box::new(Node::Union(nodes))
+    }
 }
 
 fn repeat(node: Node) -> Box<Node> {
     Box::new(Node::Repeat(Box::new(node)))
 }
 
-fn consume(tokens: &mut VecDeque<Token>, token: Token) -> Result<(), String> {
-    let next = tokens.pop_front().ok_or("Unexpected end of tokens".to_string())?;
+fn one_or_more(node: Node) -> Box<Node> {
+    Box::new(Node::OneOrMore(Box::new(node)))
+}
+
+fn optional(node: Node) -> Box<Node> {
+    Box::new(Node::Optional(Box::new(node)))
+}
+
+/**
+ * @param span: the part of the pattern to blame if the size exceeds the limit
+ */
+fn check_size(size: Option<usize>, config: &Config, tokens: &Tokens, span: Range<usize>) -> Result<(), Error> {
+    match size {
+        Some(size) if size <= config.size_limit => Ok(()),
+        _ => Err(tokens.error(Error::TooLarge, span, format!("pattern is too large: it exceeds the size limit of {} states", config.size_limit)))
+    }
+}
+
+/**
+ * @brief expands `node{min,max}` into copies of the node, e.g. `a{2,4}` into `aa` followed by at most 2 more,
+ *        right after popping the repetition token
+ */
+fn repetition(node: Node, min: u32, max: Option<u32>, config: &Config, tokens: &Tokens) -> Result<Box<Node>, Error> {
+    check_size(repetition_size(nfa_size(&node), min, max), config, tokens, tokens.last_span())?;
+
+    let mut nodes = vec![node.clone(); min as usize];
+    match max {
+        None if min == 0 => return Ok(repeat(node)),
+        None => nodes.push(*repeat(node)),
+        Some(max) if max > min => nodes.push(Node::AtMost((max - min) as usize, Box::new(node))),
+        Some(_) => {}
+    }
+
+    // `a{0}` matches the empty string only
+    Ok(concat(nodes))
+}
+
+/**
+ * @param size: the number of NFA states of the node repeated
+ * @return: the number of NFA states of the node repetition expands `node{min,max}` into, or None on overflow,
+ *          so that it is checked against the limit before the copies are made
+ */
+fn repetition_size(size: usize, min: u32, max: Option<u32>) -> Option<usize> {
+    // the part following the min copies, the repetition of the node or at most the rest of the copies
+    let rest = match max {
+        None => Some(size.checked_add(2)?),
+        Some(max) if max > min => Some(((max - min) as usize).checked_mul(size)?.checked_add(2)?),
+        Some(_) => None
+    };
+    let parts = min as usize + rest.is_some() as usize;
+    let size = (min as usize).checked_mul(size)?.checked_add(rest.unwrap_or(0))?;
+
+    // the concatenation of two or more parts has a start and an accept state of its own, and no part is the empty string
+    match parts {
+        0 => Some(2),
+        1 => Some(size),
+        _ => size.checked_add(2)
+    }
+}
+
+fn is_quantifier(token: &Token) -> bool {
+    matches!(token, Token::Asterisk | Token::Plus | Token::Question | Token::Repetition(_, _))
+}
+
+// applies a quantifier to the node, collapsing stacked quantifiers such as `a+*` into a single one
+fn quantify(node: Node, quantifier: &Token) -> Box<Node> {
+    match (node, quantifier) {
+        (Node::Repeat(child), _) => repeat(*child),
+        (Node::OneOrMore(child), Token::Plus) => one_or_more(*child),
+        (Node::OneOrMore(child), _) => repeat(*child),
+        (Node::Optional(child), Token::Question) => optional(*child),
+        (Node::Optional(child), _) => repeat(*child),
+        (node, Token::Plus) => one_or_more(node),
+        (node, Token::Question) => optional(node),
+        (node, _) => repeat(node)
+    }
+}
+
+/**
+ * @brief records the error in check mode, where parsing goes on after it
+ * @return: the error itself outside check mode
+ */
+fn report(error: Error, state: &mut ParseState) -> Result<(), Error> {
+    match state.errors.as_mut() {
+        Some(errors) => {
+            errors.push(error);
+            Ok(())
+        }
+        None => Err(error)
+    }
+}
+
+/**
+ * @brief records the error in check mode and skips the tokens up to the next one of sync outside
+ *        the groups and brackets skipped, or up to EOF
+ * @return: the error itself outside check mode
+ */
+fn recover(error: Error, tokens: &mut Tokens, state: &mut ParseState, sync: &[Token]) -> Result<(), Error> {
+    report(error, state)?;
+
+    let mut depth = 0;
+    while let Some(token) = tokens.front() {
+        match token {
+            Token::EOF => break,
+            token if depth == 0 && sync.contains(token) => break,
+            Token::LParen | Token::NamedGroup(_) | Token::NonCapturing | Token::FlagGroup(_) | Token::LBracket => depth += 1,
+            Token::RParen | Token::RBracket if depth > 0 => depth -= 1,
+            _ => {}
+        }
+        tokens.pop_front();
+    }
+    Ok(())
+}
+
+/**
+ * @param span: the part of the pattern opening the level beyond the limit
+ */
+fn too_deep(tokens: &Tokens, config: &Config, span: Range<usize>) -> Error {
+    tokens.error(Error::TooDeep, span, format!("pattern is nested too deeply: it exceeds the nesting limit of {}", config.nest_limit))
+}
+
+fn consume(tokens: &mut Tokens, token: Token) -> Result<(), Error> {
+    let span = tokens.next_span();
+    let next = tokens.pop_front().ok_or_else(|| unexpected_end(tokens))?;
 
     if next == token {
         Ok(())
     }
     else {
-        Err(format!("Expected token: {}, found: {}", token, next))
+        Err(tokens.error(Error::UnexpectedToken, span, format!("expected {}, found {}", token, next)))
     }
 }
 
-fn build_union_btree(start_char: char, end_char: char) -> Box<Node> {
-    let diff = (end_char as u32).checked_sub(start_char as u32).unwrap();
-    if diff == 0 {
-        character(start_char)
+// the tokens always end with EOF, so running out of them is a bug of the parser
+fn unexpected_end(tokens: &Tokens) -> Error {
+    tokens.error(Error::UnexpectedToken, tokens.next_span(), "unexpected end of tokens")
+}
+
+// the tokenizer turns the meta characters inside brackets into ordinary characters
+fn class_char(token: &Token) -> Option<char> {
+    match token {
+        Token::Char(c) => Some(*c),
+        _ => None
     }
-    else if diff == 1 {
-        union(*character(start_char), *character(end_char))
+}
+
+fn perl_class(class: PerlClass, config: &Config) -> Class {
+    Class::from_table(match (class, config.unicode) {
+        (PerlClass::Digit, true) => PERL_DIGIT,
+        (PerlClass::Word, true) => PERL_WORD,
+        (PerlClass::Space, true) => PERL_SPACE,
+        (PerlClass::Digit, false) => POSIX_DIGIT,
+        (PerlClass::Word, false) => POSIX_WORD,
+        (PerlClass::Space, false) => POSIX_SPACE
+    })
+}
+
+fn posix_class(class: PosixClass, config: &Config) -> Class {
+    Class::from_table(match (class, config.unicode) {
+        (PosixClass::Alnum, true) => XPOSIX_ALNUM,
+        (PosixClass::Alpha, true) => XPOSIX_ALPHA,
+        (PosixClass::Blank, true) => XPOSIX_BLANK,
+        (PosixClass::Cntrl, true) => XPOSIX_CNTRL,
+        (PosixClass::Digit, true) => XPOSIX_DIGIT,
+        (PosixClass::Graph, true) => XPOSIX_GRAPH,
+        (PosixClass::Lower, true) => XPOSIX_LOWER,
+        (PosixClass::Print, true) => XPOSIX_PRINT,
+        (PosixClass::Punct, true) => XPOSIX_PUNCT,
+        (PosixClass::Space, true) => XPOSIX_SPACE,
+        (PosixClass::Upper, true) => XPOSIX_UPPER,
+        (PosixClass::Word, true) => XPOSIX_WORD,
+        (PosixClass::XDigit, true) => XPOSIX_XDIGIT,
+        (PosixClass::Alnum, false) => POSIX_ALNUM,
+        (PosixClass::Alpha, false) => POSIX_ALPHA,
+        (PosixClass::Blank, false) => POSIX_BLANK,
+        (PosixClass::Cntrl, false) => POSIX_CNTRL,
+        (PosixClass::Digit, false) => POSIX_DIGIT,
+        (PosixClass::Graph, false) => POSIX_GRAPH,
+        (PosixClass::Lower, false) => POSIX_LOWER,
+        (PosixClass::Print, false) => POSIX_PRINT,
+        (PosixClass::Punct, false) => POSIX_PUNCT,
+        (PosixClass::Space, false) => POSIX_SPACE,
+        (PosixClass::Upper, false) => POSIX_UPPER,
+        (PosixClass::Word, false) => POSIX_WORD,
+        (PosixClass::XDigit, false) => POSIX_XDIGIT,
+        (PosixClass::Ascii, _) => ASCII
+    })
+}
+
+// the set of chars a named class such as `\d` or `[:alpha:]` stands for, and whether it is negated
+fn named_class(token: &Token, config: &Config) -> Option<(Class, bool)> {
+    match *token {
+        Token::PerlClass(class, negated) => Some((perl_class(class, config), negated)),
+        Token::PosixClass(class, negated) => Some((posix_class(class, config), negated)),
+        Token::UnicodeClass(ref name, negated) => Some((Class::from_table(property(name)?), negated)),
+        _ => None
+    }
+}
+
+/**
+ * @return: the chars the char, the range or the named class in brackets stands for, folded under the `i` flag
+ */
+fn class_item_set(class: Class, negated: bool, state: &ParseState) -> Class {
+    match (state.flags.case_insensitive, negated) {
+        (false, false) => class,
+        (false, true) => class.complement(),
+        (true, false) => class.case_fold(),
+        // a char matches `\W` regardless of case unless all its variants are word chars
+        (true, true) => class.case_interior().complement()
+    }
+}
+
+// makes the char match regardless of case under the `i` flag
+fn case_char(c: char, state: &ParseState) -> Box<Node> {
+    if state.flags.case_insensitive && !case_folding(c).is_empty() {
+        class(Class::char(c).case_fold())
     }
     else {
-        let mid = (start_char as u32 + end_char as u32) / 2;
-        let node1 = build_union_btree(start_char, char::from_u32(mid).unwrap());
-        let node2 = build_union_btree(char::from_u32(mid + 1).unwrap(), end_char);
-        union(*node1, *node2)
+        character(c)
     }
 }
 
 /*
 expr            := subexpr EOF
 subexpr         := seq '|' subexpr | seq
-seq             := subseq | ''
-subseq          := star subseq | star
-star            := factor '*' | factor
-factor          := '(' subexpr ')' | CHARACTER | '[' charset_inner ']'
-charset_inner   := CHARACTER charset_inner | CHARACTER '-' CHARACTER charset_inner | ''
+seq             := SET_FLAGS* subseq | SET_FLAGS* ''
+subseq          := star SET_FLAGS* subseq | star SET_FLAGS*
+star            := star '*' | star '+' | star '?' | star '{' m ',' n '}' | factor
+factor          := '(' subexpr ')' | '(?P<' NAME '>' subexpr ')' | '(?:' subexpr ')' | '(?' FLAGS ':' subexpr ')' | CHARACTER | PERL_CLASS | UNICODE_CLASS | bracket | '.' | '^' | '$' | '\A' | '\z' | '\b' | '\B'
+bracket         := '[' class_inner ']' | '[' '^' class_inner ']'
+class_inner     := class_union '&&' class_inner | class_union '--' class_inner | class_union
+class_union     := class_item class_union | class_item
+class_item      := CHARACTER | CHARACTER '-' CHARACTER | NAMED_CLASS | bracket
+NAMED_CLASS     := PERL_CLASS | UNICODE_CLASS | '[:' POSIX_CLASS ':]'
 */
 
 
-fn charset_inner(tokens: &mut VecDeque<Token>) -> Result<Box<Node>, String> {
-    let token = tokens.pop_front().ok_or("Unexpected end of tokens".to_string())?;
+// the operation of `&&` or `--` on the sets on either side
+type ClassOperation = fn(&Class, &Class) -> Class;
 
-    if let Token::Char(c) = token {
-        // charset_inner := CHARACTER charset_inner
-        if let Some(&Token::Char(_next)) = tokens.front() {
-            Ok(union(*character(c), *charset_inner(tokens)?))
-        }
-        // charset_inner := CHARACTER '-' CHARACTER charset_inner
-        else if let Some(&Token::Hyphen) = tokens.front() {
-            consume(tokens, Token::Hyphen)?;
-            let start_char = c;
-            let end_char = if let Token::Char(cc) = tokens.pop_front().ok_or("Unexpected end of tokens".to_string())? {
-                cc
-            }
-            else {
-                return Err(format!("Unexpected meta character"));
-            };
+// a bracket opened and not closed yet
+struct OpenBracket {
+    // the span of the `[`
+    open: Range<usize>,
+    negated: bool,
+    // the classes before the last `&&` or `--` combined and the operation of that operator, None in the first union
+    left: Option<(Class, ClassOperation)>,
+    // the union parsed so far after the last `&&` or `--`, and whether it has an item yet
+    union: Class,
+    started: bool
+}
 
-            let node1 = build_union_btree(start_char, end_char);
+/**
+ * @brief opens the brackets right after `[` unless they are nested beyond the limit
+ * @return: whether the brackets are opened, as in check mode the ones beyond the limit are skipped whole
+ */
+fn open_bracket(tokens: &mut Tokens, config: &Config, state: &mut ParseState, brackets: &mut Vec<OpenBracket>) -> Result<bool, Error> {
+    let open = tokens.last_span();
 
-            if let Some(Token::RBracket) = tokens.front() {
-                Ok(node1)
-            }
-            else {
-                let node2 = charset_inner(tokens)?;
-                Ok(union(*node1, *node2))
-            }
-        }
-        // end of charset_inner
-        else if let Some(&Token::RBracket) = tokens.front() {
-            Ok(character(c))
-        }
-        // unexpected token
-        else if let Some(t) = tokens.front() {
-            Err(format!("Unexpected token \"{}\"", t))
-        }
-        // end of token
-        else {
-            Err("Unexpected end of tokens".to_string())
+    if state.depth >= config.nest_limit {
+        recover(too_deep(tokens, config, open), tokens, state, &[Token::RBracket])?;
+        if tokens.front() == Some(&Token::RBracket) {
+            tokens.pop_front();
         }
+        return Ok(false);
     }
-    else {
-        Err(format!("Unexpected token \"{}\"", token))
+
+    // bracket := '[' '^' class_inner ']'
+    let negated = tokens.front() == Some(&Token::Hat);
+    if negated {
+        consume(tokens, Token::Hat)?;
     }
+
+    state.depth += 1;
+    brackets.push(OpenBracket {
+        open,
+        negated,
+        left: None,
+        union: Class::new([]),
+        started: false
+    });
+    Ok(true)
 }
 
+/**
+ * @brief parses the brackets right after `[`, keeping the nested ones on an explicit stack rather than
+ *        recursing into them, where `&&` and `--` bind looser than the union and associate to the left
+ */
+fn bracket(tokens: &mut Tokens, config: &Config, state: &mut ParseState) -> Result<Class, Error> {
+    let mut brackets: Vec<OpenBracket> = Vec::new();
+    if !open_bracket(tokens, config, state, &mut brackets)? {
+        return Ok(Class::new([]));
+    }
 
-fn charset_inner_neg(tokens: &mut VecDeque<Token>) -> Result<HashSet<char>, String> {
-    let token = tokens.pop_front().ok_or("Unexpected end of tokens".to_string())?;
+    loop {
+        let top = brackets.last_mut().unwrap();
 
-    if let Token::Char(c) = token {
-        // charset_inner := CHARACTER charset_inner
-        if let Some(&Token::Char(_next)) = tokens.front() {
-            let set1 = neg_character(c);
-            let set2 = charset_inner_neg(tokens)?;
+        let item = match tokens.front() {
+            // class_inner := class_union '&&' class_inner | class_union '--' class_inner | class_union,
+            // where the union takes at least one item and the brackets are unclosed at EOF
+            Some(token @ (Token::RBracket | Token::ClassAnd | Token::ClassMinus | Token::EOF)) if top.started || *token == Token::EOF => {
+                let union = std::mem::replace(&mut top.union, Class::new([]));
+                top.started = false;
+                let set = match top.left.take() {
+                    Some((left, operation)) => operation(&left, &union),
+                    None => union
+                };
 
-            Ok(set1.union(&set2).cloned().collect())
-        }
-        // charset_inner := CHARACTER '-' CHARACTER charset_inner
-        else if let Some(&Token::Hyphen) = tokens.front() {
-            consume(tokens, Token::Hyphen)?;
-            let start_char = c;
-            let end_char = if let Token::Char(cc) = tokens.pop_front().ok_or("Unexpected end of tokens".to_string())? {
-                cc
-            }
-            else {
-                return Err(format!("Unexpected meta character"));
-            };
-
-            let set1 = {
-                let mut set = HashSet::new();
-                for c in start_char..=end_char {
-                    set.insert(c);
+                let operation: Option<ClassOperation> = match tokens.front() {
+                    Some(Token::ClassAnd) => Some(Class::intersection),
+                    Some(Token::ClassMinus) => Some(Class::difference),
+                    _ => None
+                };
+                if let Some(operation) = operation {
+                    let operator = tokens.pop_front().unwrap();
+                    if !missing_operand(operator, tokens, state)? {
+                        top.left = Some((set, operation));
+                        continue;
+                    }
                 }
-                set
-            };
 
-            if let Some(Token::RBracket) = tokens.front() {
-                Ok(set1)
+                let bracket = brackets.pop().unwrap();
+                state.depth -= 1;
+                let start = bracket.open.start;
+                let set = close_bracket(bracket, set, tokens);
+                if brackets.is_empty() {
+                    return set;
+                }
+                // the nested brackets are an item of the enclosing ones
+                match set {
+                    Ok(set) => class_end(set, start, tokens),
+                    Err(e) => Err(e)
+                }
             }
-            else {
-                let set2 = charset_inner_neg(tokens)?;
-                Ok(set1.union(&set2).cloned().collect())
+            // class_item := bracket
+            Some(Token::LBracket) => {
+                tokens.pop_front();
+                let start = tokens.last_span().start;
+                if open_bracket(tokens, config, state, &mut brackets)? {
+                    continue;
+                }
+                class_end(Class::new([]), start, tokens)
             }
+            None => return Err(unexpected_end(tokens)),
+            _ => class_item(tokens, config, state)
+        };
+
+        // class_union := class_item class_union | class_item
+        // where in check mode, parsing goes on from the next `]`, `&&` or `--` after an invalid item
+        let top = brackets.last_mut().unwrap();
+        match item {
+            Ok(item) => top.union = top.union.union(&item),
+            Err(e) => recover(e, tokens, state, &[Token::RBracket, Token::ClassAnd, Token::ClassMinus])?
         }
-        // end of charset_inner
-        else if let Some(&Token::RBracket) = tokens.front() {
-            Ok(neg_character(c))
+        top.started = true;
+    }
+}
+
+/**
+ * @param set: the chars the brackets hold, before negation
+ * @return: the chars the brackets match once they are closed by `]`
+ */
+fn close_bracket(bracket: OpenBracket, set: Class, tokens: &mut Tokens) -> Result<Class, Error> {
+    if tokens.front() == Some(&Token::EOF) {
+        return Err(unclosed_class(tokens, bracket.open));
+    }
+    consume(tokens, Token::RBracket)?;
+
+    Ok(if bracket.negated { set.complement() } else { set })
+}
+
+/**
+ * @brief reports the `&&` or `--` just consumed if the `]` follows it, leaving it nothing to operate on
+ * @return: whether the operand is missing, when in check mode
+ */
+fn missing_operand(operator: Token, tokens: &Tokens, state: &mut ParseState) -> Result<bool, Error> {
+    if tokens.front() != Some(&Token::RBracket) {
+        return Ok(false);
+    }
+    report(tokens.error(Error::UnexpectedToken, tokens.last_span(), format!("{} has no class after it to operate on", operator)), state)?;
+    Ok(true)
+}
+
+fn unclosed_class(tokens: &Tokens, open: Range<usize>) -> Error {
+    tokens.error(Error::UnclosedClass, open, "[ is not closed by ]")
+}
+
+fn class_item(tokens: &mut Tokens, config: &Config, state: &mut ParseState) -> Result<Class, Error> {
+    let token = tokens.pop_front().ok_or_else(|| unexpected_end(tokens))?;
+    let start = tokens.last_span().start;
+
+    // a malformed escape stands for no char, as the tokenizer has reported it
+    let start_set = class_char(&token).map_or(Class::new([]), Class::char);
+
+    // class_item := CHARACTER '-' CHARACTER
+    if (class_char(&token).is_some() || token == Token::Invalid) && tokens.front() == Some(&Token::Hyphen) {
+        consume(tokens, Token::Hyphen)?;
+        // the `-` is left right before EOF only where a malformed escape ending the pattern was dropped,
+        // and then it is an ordinary character of the brackets left unclosed
+        if tokens.front() == Some(&Token::EOF) {
+            return Ok(class_item_set(start_set.union(&Class::char('-')), false, state));
         }
-        // unexpected token
-        else if let Some(t) = tokens.front() {
-            Err(format!("Unexpected token \"{}\"", t))
+        let end_token = tokens.pop_front().ok_or_else(|| unexpected_end(tokens))?;
+        let span = start..tokens.last_span().end;
+
+        let (c, end_char) = match (class_char(&token), class_char(&end_token)) {
+            (Some(c), Some(end_char)) => (c, end_char),
+            // a range with a malformed escape at either end is left unchecked
+            _ if token == Token::Invalid || end_token == Token::Invalid => return Ok(Class::new([])),
+            _ => return Err(tokens.error(Error::BadRange, span, format!("invalid range {}-{}: a class cannot end a range", token, end_token)))
+        };
+        if c > end_char {
+            return Err(tokens.error(Error::BadRange, span, format!("invalid range {}-{}: the start exceeds the end", c, end_char)));
         }
-        // end of token
-        else {
-            Err("Unexpected end of tokens".to_string())
+        // the surrogates are not chars, so a range over them is split in two by hand like `[\0-\u{D7FF}\u{E000}-\u{10FFFF}]`
+        if c <= '\u{D7FF}' && end_char >= '\u{E000}' {
+            return Err(tokens.error(Error::BadRange, span, format!("invalid range U+{:X}-U+{:X}: it spans the surrogates U+D800-U+DFFF", c as u32, end_char as u32)));
         }
+        Ok(class_item_set(Class::new([(c, end_char)]), false, state))
+    }
+    // class_item := CHARACTER
+    else if class_char(&token).is_some() || token == Token::Invalid {
+        Ok(class_item_set(start_set, false, state))
+    }
+    // class_item := NAMED_CLASS
+    else if let Some((set, negated)) = named_class(&token, config) {
+        class_end(class_item_set(set, negated, state), start, tokens)
+    }
+    // a hyphen right after a range, e.g. the second one of `[a-c-e]`
+    else if token == Token::Hyphen {
+        Err(tokens.error(Error::BadRange, tokens.last_span(), "invalid range: - follows a range, so escape it to match it"))
     }
     else {
-        Err(format!("Unexpected token \"{}\"", token))
+        Err(tokens.error(Error::UnexpectedToken, tokens.last_span(), format!("unexpected token {}", token)))
     }
 }
 
+/**
+ * @brief checks the named class or the brackets just parsed as an item do not start a range,
+ *        while `-` right before `]` is an ordinary character as in `[\w-]`
+ * @param start: where the item starts in the pattern
+ */
+fn class_end(set: Class, start: usize, tokens: &mut Tokens) -> Result<Class, Error> {
+    if let Some(&Token::Hyphen) = tokens.front() {
+        consume(tokens, Token::Hyphen)?;
+        let end = tokens.next_span().end;
+        return Err(tokens.error(Error::BadRange, start..end, "invalid range: a class cannot start a range"));
+    }
+    Ok(set)
+}
 
-fn factor(tokens: &mut VecDeque<Token>) -> Result<Box<Node>, String> {
-    let token = tokens.pop_front().ok_or( "Unexpected end of tokens".to_string())?;
 
-    // factor := '(' subexpr ')'
-    if token == Token::LParen {
-        let ret = Ok(subexpr(tokens)?);
-        consume(tokens, Token::RParen)?;
-        ret
-    }
+fn factor(tokens: &mut Tokens, config: &Config, state: &mut ParseState) -> Result<Box<Node>, Error> {
+    let token = tokens.pop_front().ok_or_else(|| unexpected_end(tokens))?;
+
     // factor := CHARACTER
-    else if let Token::Char(c) = token {
-        Ok(character(c))
+    if let Token::Char(c) = token {
+        Ok(case_char(c, state))
     }
-    // factor := '[' charset_inner ']' | '[' '^' charset_inner ']'
+    // a malformed escape the tokenizer has reported matches the empty string in check mode
+    else if token == Token::Invalid {
+        Ok(empty())
+    }
+    // factor := bracket
     else if token == Token::LBracket {
-        // factor := '[' '^' charset_inner ']'
-        if let Some(&Token::Hat) = tokens.front() {
-            consume(tokens, Token::Hat)?;
-            let set = charset_inner_neg(tokens)?;
-            consume(tokens, Token::RBracket)?;
-            Ok(Box::new(Node::NegChar(set)))
+        Ok(class(bracket(tokens, config, state)?))
+    }
+    // factor := '.'
+    else if token == Token::Dot {
+        if state.flags.dot_matches_new_line {
+            Ok(class(Class::new([('\0', char::MAX)])))
+        }
+        else if state.flags.crlf {
+            Ok(class(Class::new([('\r', '\r'), ('\n', '\n')]).complement()))
         }
-        // factor := '[' charset_inner ']'
         else {
-            let node = charset_inner(tokens)?;
-            consume(tokens, Token::RBracket)?;
-            Ok(node)
+            Ok(class(Class::char('\n').complement()))
         }
     }
-    // factor := '.'
-    else if token == Token::Dot {
-        Ok(Box::new(Node::NegChar(HashSet::new())))
+    // factor := '^' | '\A'
+    else if token == Token::Hat && state.flags.multi_line && state.flags.crlf {
+        Ok(Box::new(Node::Look(Look::StartLineCrlf)))
+    }
+    else if token == Token::Hat && state.flags.multi_line {
+        Ok(Box::new(Node::Look(Look::StartLine)))
+    }
+    else if token == Token::Hat || token == Token::StartText {
+        Ok(Box::new(Node::Look(Look::StartText)))
+    }
+    // factor := '$' | '\z'
+    else if token == Token::Dollar && state.flags.multi_line && state.flags.crlf {
+        Ok(Box::new(Node::Look(Look::EndLineCrlf)))
+    }
+    else if token == Token::Dollar && state.flags.multi_line {
+        Ok(Box::new(Node::Look(Look::EndLine)))
+    }
+    else if token == Token::Dollar || token == Token::EndText {
+        Ok(Box::new(Node::Look(Look::EndText)))
+    }
+    // factor := PERL_CLASS | UNICODE_CLASS
+    else if let Some((set, negated)) = named_class(&token, config) {
+        Ok(class(class_item_set(set, negated, state)))
+    }
+    // factor := '\b', where the word chars are those of `\w`
+    else if token == Token::WordBoundary && !config.unicode {
+        Ok(Box::new(Node::Look(Look::WordBoundaryAscii)))
+    }
+    else if token == Token::WordBoundary {
+        Ok(Box::new(Node::Look(Look::WordBoundary)))
+    }
+    // factor := '\B'
+    else if token == Token::NotWordBoundary && !config.unicode {
+        Ok(Box::new(Node::Look(Look::NotWordBoundaryAscii)))
+    }
+    else if token == Token::NotWordBoundary {
+        Ok(Box::new(Node::Look(Look::NotWordBoundary)))
+    }
+    // a quantifier must follow something to repeat, while in check mode it repeats the empty string
+    else if is_quantifier(&token) {
+        report(tokens.error(Error::DanglingQuantifier, tokens.last_span(), format!("quantifier {} does not follow any expression", token)), state)?;
+        Ok(empty())
     }
     // error
     else {
-        Err(format!("unexpected token: {}", token))
+        Err(tokens.error(Error::UnexpectedToken, tokens.last_span(), format!("unexpected token {}", token)))
     }
 }
 
-fn star(tokens: &mut VecDeque<Token>) -> Result<Box<Node>, String> {
-    let node = factor(tokens)?;
-    
-    if let Some(token) = tokens.front() {
-        // star := factor '*'
-        if *token == Token::Asterisk {
-            consume(tokens, Token::Asterisk)?;
-            let ret = Ok(repeat(*node));
-            ret
-        }
-        // star := factor
-        else {
-            Ok(node)
-        }
-    }
-    else {
-        Ok(node)
+// applies the `(?flags)` tokens in front, which take effect up to the end of the enclosing group
+fn set_flags(tokens: &mut Tokens, state: &mut ParseState) {
+    while let Some(Token::SetFlags(flags)) = tokens.front() {
+        state.flags.set(flags);
+        tokens.pop_front();
     }
 }
 
-fn seq(tokens: &mut VecDeque<Token>) -> Result<Box<Node>, String> {
-    // seq := subseq | ''
-    if let Some(token) = tokens.front() {
-        match *token {
-            // seq := subseq
-            Token::LParen | Token::Char(_) | Token::LBracket | Token::Dot => {
-                subseq(tokens)
-            }
-            // seq := ''
-            _ => {
-                Ok(character('\0'))
+fn star(tokens: &mut Tokens, config: &Config, state: &mut ParseState) -> Result<Box<Node>, Error> {
+    // star := factor
+    let node = factor(tokens, config, state)?;
+    quantifiers(node, tokens, config, state)
+}
+
+// star := star '*' | star '+' | star '?' | star '{' m ',' n '}'
+// where each counted repetition nests the node one level deeper, unlike the quantifiers collapsing into one
+fn quantifiers(mut node: Box<Node>, tokens: &mut Tokens, config: &Config, state: &mut ParseState) -> Result<Box<Node>, Error> {
+    let mut depth = state.depth;
+    // the quantifier applied last, if any
+    let mut last: Option<Token> = None;
+
+    while let Some(token) = tokens.front() {
+        if let Token::Repetition(min, max) = *token {
+            let quantifier = tokens.pop_front().unwrap();
+            if depth >= config.nest_limit {
+                return Err(too_deep(tokens, config, tokens.last_span()));
             }
+            depth += 1;
+            node = repetition(*node, min, max, config, tokens)?;
+            last = Some(quantifier);
+        }
+        // a `?` right after a quantifier would make it lazy, which the leftmost-longest DFA cannot honour
+        else if let (Token::Question, Some(previous)) = (token, &last) {
+            let message = format!("lazy quantifier {}? is not supported", previous);
+            tokens.pop_front();
+            report(tokens.error(Error::UnexpectedToken, tokens.last_span(), message), state)?;
+        }
+        else if is_quantifier(token) {
+            let quantifier = tokens.pop_front().unwrap();
+            node = quantify(*node, &quantifier);
+            last = Some(quantifier);
+        }
+        else {
+            break;
         }
     }
-    else {
-        Err("Unexpected end of tokens".to_string())
-    }
+
+    Ok(node)
 }
 
+// the tokens a factor other than a group or a dangling quantifier starts with
+fn starts_factor(token: &Token) -> bool {
+    matches!(token, Token::Char(_) | Token::Invalid | Token::LBracket | Token::Dot | Token::Hat | Token::Dollar | Token::StartText | Token::EndText | Token::WordBoundary | Token::NotWordBoundary | Token::PerlClass(_, _) | Token::UnicodeClass(_, _))
+}
 
+// a group opened and not closed yet, with the alternatives and the sequence it interrupted
+struct OpenGroup {
+    // the span of the opening token
+    open: Range<usize>,
+    // the capture index, None for the non-capturing groups
+    index: Option<usize>,
+    // the flags outside the group, restored at its `)`
+    outer: Flags,
+    alternatives: Vec<Node>,
+    nodes: Vec<Node>
+}
 
-fn subseq(tokens: &mut VecDeque<Token>) -> Result<Box<Node>, String> {
-    // subseq  := star subseq | star
-    let node = star(tokens)?;
+/**
+ * @brief parses subexpr up to EOF or a `)` without the matching `(`, keeping the open groups on an explicit stack
+ *        rather than recursing into them and looping over seq and subseq, so that neither deep nesting
+ *        nor a long pattern overflows the call stack
+ */
+fn subexpr(tokens: &mut Tokens, config: &Config, state: &mut ParseState) -> Result<Box<Node>, Error> {
+    let mut groups: Vec<OpenGroup> = Vec::new();
+    // the alternatives and the sequence parsed so far in the innermost open group
+    let mut alternatives = Vec::new();
+    let mut nodes = Vec::new();
 
-    if let Some(token) = tokens.front() {
-        match *token {
-            // subseq := star subseq
-            Token::LParen | Token::Char(_) | Token::LBracket | Token::Dot => {
-                Ok(concat(*node, *subseq(tokens)?))
-            }
-            // subseq := star
-            _ => {
-                Ok(node)
-            }
-        }
-    }
-    else {
-        Err("Unexpected end of tokens".to_string())
-    }
-}
+    loop {
+        // seq := SET_FLAGS* subseq, subseq := star SET_FLAGS* subseq
+        set_flags(tokens, state);
 
-fn subexpr(tokens: &mut VecDeque<Token>) -> Result<Box<Node>, String> {
-    // subexpr := seq '|' subexpr | seq
-    let node = seq(tokens)?;
+        match tokens.front() {
+            // factor := '(' subexpr ')' | '(?P<' NAME '>' subexpr ')' | '(?:' subexpr ')' | '(?' FLAGS ':' subexpr ')'
+            Some(Token::LParen | Token::NamedGroup(_) | Token::NonCapturing | Token::FlagGroup(_)) => {
+                let token = tokens.pop_front().unwrap();
+                let open = tokens.last_span();
+
+                // in check mode, the groups beyond the nesting limit are skipped whole
+                if state.depth >= config.nest_limit {
+                    recover(too_deep(tokens, config, open), tokens, state, &[Token::RParen])?;
+                    if tokens.front() == Some(&Token::RParen) {
+                        tokens.pop_front();
+                    }
+                    match quantifiers(empty(), tokens, config, state) {
+                        Ok(node) => nodes.push(*node),
+                        Err(e) => recover(e, tokens, state, &[Token::RParen, Token::VBar])?
+                    }
+                    continue;
+                }
 
-    if let Some(token) = tokens.front() {
-        match *token {
+                let (index, flags) = match token {
+                    Token::LParen | Token::NamedGroup(_) => {
+                        let name = match token {
+                            Token::NamedGroup(name) if state.groups.contains(&Some(name.clone())) => {
+                                report(tokens.error(Error::BadGroup, open.clone(), format!("duplicate group name {}", name)), state)?;
+                                Some(name)
+                            }
+                            Token::NamedGroup(name) => Some(name),
+                            _ => None
+                        };
+                        state.groups.push(name);
+                        (Some(state.groups.len()), Vec::new())
+                    }
+                    Token::FlagGroup(flags) => (None, flags),
+                    _ => (None, Vec::new())
+                };
+
+                groups.push(OpenGroup {
+                    open,
+                    index,
+                    outer: state.flags,
+                    alternatives: std::mem::take(&mut alternatives),
+                    nodes: std::mem::take(&mut nodes)
+                });
+                // the flags are set only inside the group
+                state.flags.set(&flags);
+                state.depth += 1;
+            }
             // subexpr := seq '|' subexpr
-            Token::VBar => {
+            Some(Token::VBar) => {
                 consume(tokens, Token::VBar)?;
-                Ok(union(*node, *subexpr(tokens)?))
+                alternatives.push(*concat(std::mem::take(&mut nodes)));
+            }
+            // the end of the innermost open group, which is unclosed at EOF
+            Some(Token::RParen | Token::EOF) if !groups.is_empty() => {
+                if tokens.front() == Some(&Token::EOF) {
+                    let open = groups.last().unwrap().open.clone();
+                    report(tokens.error(Error::UnclosedGroup, open, "( is not closed by )"), state)?;
+                }
+                else {
+                    consume(tokens, Token::RParen)?;
+                }
+
+                let group = groups.pop().unwrap();
+                alternatives.push(*concat(std::mem::take(&mut nodes)));
+                let mut node = union(std::mem::replace(&mut alternatives, group.alternatives));
+                if let Some(index) = group.index {
+                    node = Box::new(Node::Group(index, node));
+                }
+                nodes = group.nodes;
+                state.flags = group.outer;
+                state.depth -= 1;
+
+                match quantifiers(node, tokens, config, state) {
+                    Ok(node) => nodes.push(*node),
+                    Err(e) => recover(e, tokens, state, &[Token::RParen, Token::VBar])?
+                }
             }
-            // subexpr := seq
-            _ => {
-                Ok(node)
+            // subseq := star subseq, which reports the dangling quantifiers too
+            Some(token) if starts_factor(token) || is_quantifier(token) => {
+                match star(tokens, config, state) {
+                    Ok(node) => nodes.push(*node),
+                    // in check mode, parsing goes on from the next `)` or `|` after an invalid star
+                    Err(e) => recover(e, tokens, state, &[Token::RParen, Token::VBar])?
+                }
             }
+            // seq := ''
+            Some(_) => break,
+            None => return Err(unexpected_end(tokens))
         }
     }
-    else {
-        Err("Unexpected end of tokens".to_string())
-    }
+
+    alternatives.push(*concat(nodes));
+    Ok(union(alternatives))
 }
 
 
-fn expr(tokens: &mut VecDeque<Token>) -> Result<Box<Node>, String> {
+fn expr(tokens: &mut Tokens, config: &Config, state: &mut ParseState) -> Result<Box<Node>, Error> {
     // expr := subexpr EOF
-    let node = subexpr(tokens)?;
+    let mut node = subexpr(tokens, config, state)?;
 
+    // in check mode, parsing goes on after a `)` without the matching `(`
+    while tokens.front() == Some(&Token::RParen) {
+        report(tokens.error(Error::UnopenedGroup, tokens.next_span(), ") is not opened by ("), state)?;
+        tokens.pop_front();
+        node = concat(vec![*node, *subexpr(tokens, config, state)?]);
+    }
+
+    // the EOF token spans the end of the pattern, so the whole pattern is blamed for its size
+    if let Err(e) = check_size(Some(nfa_size(&node)), config, tokens, 0..tokens.next_span().end) {
+        report(e, state)?;
+    }
     consume(tokens, Token::EOF)?;
 
     if tokens.is_empty() {
         Ok(node)
     }
     else {
-        Err("Unexpected end of tokens".to_string())
+        Err(unexpected_end(tokens))
     }
 }
 
@@ -330,37 +964,54 @@ pub fn print_node_child(root: &Node, i: usize) -> usize {
     let mut node_num = i;
 
     match root {
+        Node::Empty => {
+            println!("\tn{} [label=\"ε\"]", i);
+        }
         Node::Char(c) => {
             println!("\tn{} [label=\"{}\"]", i, if *c == '\0' { "\\0".to_string() } else { c.to_string() });
         }
-        Node::Concat((child1, child2)) => {
-            println!("\tn{} [label=\"Concat\"]", i);
-            let child1_num = print_node_child(child1, node_num + 1);
+        Node::Concat(children) | Node::Union(children) => {
+            println!("\tn{} [label=\"{}\"]", i, if let Node::Concat(_) = root { "Concat" } else { "Union" });
+            for child in children {
+                println!("\tn{} -> n{}", i, node_num + 1);
+                node_num = print_node_child(child, node_num + 1);
+            }
+        }
+        Node::Repeat(child) => {
+            println!("\tn{} [label=\"Repeat\"]", i);
+            let child_num = print_node_child(child, node_num + 1);
+            println!("\tn{} -> n{}", i, node_num + 1);
+            node_num = child_num;
+        }
+        Node::OneOrMore(child) => {
+            println!("\tn{} [label=\"OneOrMore\"]", i);
+            let child_num = print_node_child(child, node_num + 1);
             println!("\tn{} -> n{}", i, node_num + 1);
-            let child2_num = print_node_child(child2, child1_num + 1);
-            println!("\tn{} -> n{}", i, child1_num + 1);
-            node_num = child2_num;
+            node_num = child_num;
         }
-        Node::Union((child1, child2)) => {
-            println!("\tn{} [label=\"Union\"]", i);
-            let child1_num = print_node_child(child1, node_num + 1);
+        Node::Optional(child) => {
+            println!("\tn{} [label=\"Optional\"]", i);
+            let child_num = print_node_child(child, node_num + 1);
             println!("\tn{} -> n{}", i, node_num + 1);
-            let child2_num = print_node_child(child2, child1_num + 1);
-            println!("\tn{} -> n{}", i, child1_num + 1);
-            node_num = child2_num;
+            node_num = child_num;
         }
-        Node::Repeat(child) => {
-            println!("\tn{} [label=\"Repeat\"]", i);
+        Node::AtMost(n, child) => {
+            println!("\tn{} [label=\"AtMost {}\"]", i, n);
             let child_num = print_node_child(child, node_num + 1);
             println!("\tn{} -> n{}", i, node_num + 1);
             node_num = child_num;
         }
-        Node::NegChar(set) => {
-            let mut s = String::new();
-            for c in set {
-                s.push(*c);
-            }
-            println!("\tn{} [label=\"NegChar: {}\"]", i, s);
+        Node::Look(look) => {
+            println!("\tn{} [label=\"{}\"]", i, look);
+        }
+        Node::Group(index, child) => {
+            println!("\tn{} [label=\"Group {}\"]", i, index);
+            let child_num = print_node_child(child, node_num + 1);
+            println!("\tn{} -> n{}", i, node_num + 1);
+            node_num = child_num;
+        }
+        Node::Class(class) => {
+            println!("\tn{} [label=\"Class: {}\"]", i, class.to_string().escape_default());
         }
     };
 
@@ -371,10 +1022,10 @@ pub fn print_node_child(root: &Node, i: usize) -> usize {
 pub fn print_node(root: &Node) {
     println!("digraph PARSE {{");
     println!("\tnode [shape=circle]");
-    println!("");
+    println!();
 
     print_node_child(root, 0);
 
-    println!("");
+    println!();
     println!("}}");
 }
@@ -11,32 +11,27 @@ Usage: {} [regex_str] [replace_str]
 [regex_str]  : regex string to search
 [replace_str]: string to substitute
 
-arguments after the first two are ignored", args.get(0).unwrap());
+arguments after the first two are ignored", args.first().unwrap());
 
     let regex_str = args.get(1).ok_or_else(|| {eprintln!("{}", usage); "regex string is not provided"})?;
     let replace_str = args.get(2).ok_or_else(|| {eprintln!("{}", usage); "replace string is not provided"})?;
-    let re = TinyRegex::new(regex_str).unwrap();
+    let re = TinyRegex::new(regex_str).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
 
     let mut buffer = String::new();
     io::stdin().read_to_string(&mut buffer)?;
 
     for line in buffer.lines() {
-        let matches = re.find_all(line);
-
-        for (i, c) in line.char_indices() {
-            if let Some((start, end)) = matches.clone().map(|mat| (mat.start(), mat.end())).find(|(start, end)| *start <= i && i < *end) {
-                if i == start {
-                    print!("{}", replace_str);
-                }
-                if i == end - 1 {
-                    continue;
-                }
-            }
-            else {
-                print!("{}", c);
-            }
+        // the end of the last match, from which the line is printed as it is up to the next match
+        let mut last = 0;
+
+        for mat in re.find_iter(line) {
+            print!("{}{}", &line[last..mat.start()], replace_str);
+            last = mat.end();
         }
-        println!();
+        println!("{}", &line[last..]);
     }
 
 
@@ -1,19 +1,109 @@
-#[allow(dead_code)]
-
 use std::collections::VecDeque;
+use std::ops::Range;
+use std::str::CharIndices;
+use crate::unicode::property;
+use crate::error::*;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PerlClass {
+    Digit,
+    Word,
+    Space
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PosixClass {
+    Alnum,
+    Alpha,
+    Ascii,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Word,
+    XDigit
+}
+
+const POSIX_CLASSES: [(&str, PosixClass); 14] = [
+    ("alnum", PosixClass::Alnum),
+    ("alpha", PosixClass::Alpha),
+    ("ascii", PosixClass::Ascii),
+    ("blank", PosixClass::Blank),
+    ("cntrl", PosixClass::Cntrl),
+    ("digit", PosixClass::Digit),
+    ("graph", PosixClass::Graph),
+    ("lower", PosixClass::Lower),
+    ("print", PosixClass::Print),
+    ("punct", PosixClass::Punct),
+    ("space", PosixClass::Space),
+    ("upper", PosixClass::Upper),
+    ("word", PosixClass::Word),
+    ("xdigit", PosixClass::XDigit)
+];
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Flag {
+    CaseInsensitive,
+    MultiLine,
+    DotMatchesNewLine,
+    Crlf,
+    Verbose
+}
+
+const FLAGS: [(char, Flag); 5] = [
+    ('i', Flag::CaseInsensitive),
+    ('m', Flag::MultiLine),
+    ('s', Flag::DotMatchesNewLine),
+    ('R', Flag::Crlf),
+    ('x', Flag::Verbose)
+];
 
 #[derive(PartialEq, Debug)]
 pub enum Token {
     Char(char),
     LParen,
+    // `(?P<name>` or `(?<name>`
+    NamedGroup(String),
+    // `(?:`
+    NonCapturing,
+    // `(?i-s)` setting or clearing flags up to the end of the enclosing group, where the tokenizer takes care of `x`
+    SetFlags(Vec<(Flag, bool)>),
+    // `(?i-s:` opening a non-capturing group with the flags
+    FlagGroup(Vec<(Flag, bool)>),
     RParen,
     LBracket,
     RBracket,
     Asterisk,
+    Plus,
+    Question,
+    Repetition(u32, Option<u32>),
     Hyphen,
+    // `&&` intersecting the sets on either side inside brackets
+    ClassAnd,
+    // `--` subtracting the set on the right from the one on the left inside brackets
+    ClassMinus,
     VBar,
     Hat,
+    Dollar,
+    StartText,
+    EndText,
+    WordBoundary,
+    NotWordBoundary,
+    // `\d`, `\w`, `\s` and their negations `\D`, `\W`, `\S`
+    PerlClass(PerlClass, bool),
+    // `[:alpha:]` and the like inside brackets, negated as `[:^alpha:]`
+    PosixClass(PosixClass, bool),
+    // `\p{Greek}` and the like, negated as `\P{Greek}` or `\p{^Greek}`
+    UnicodeClass(String, bool),
     Dot,
+    // a malformed escape or class the tokenizer has reported, standing for no char so that the rest of the pattern
+    // can still be checked
+    Invalid,
     EOF
 }
 
@@ -23,48 +113,605 @@ impl std::fmt::Display for Token {
         match self {
             Token::Char(c) => write!(f, "{}", c),
             Token::LParen => write!(f, "("),
+            Token::NamedGroup(name) => write!(f, "(?P<{}>", name),
+            Token::NonCapturing => write!(f, "(?:"),
+            Token::SetFlags(flags) => write!(f, "(?{})", display_flags(flags)),
+            Token::FlagGroup(flags) => write!(f, "(?{}:", display_flags(flags)),
             Token::RParen => write!(f, ")"),
             Token::LBracket => write!(f, "["),
             Token::RBracket => write!(f, "]"),
             Token::Asterisk => write!(f, "*"),
+            Token::Plus => write!(f, "+"),
+            Token::Question => write!(f, "?"),
+            Token::Repetition(min, Some(max)) if min == max => write!(f, "{{{}}}", min),
+            Token::Repetition(min, Some(max)) => write!(f, "{{{},{}}}", min, max),
+            Token::Repetition(min, None) => write!(f, "{{{},}}", min),
             Token::Hyphen => write!(f, "-"),
+            Token::ClassAnd => write!(f, "&&"),
+            Token::ClassMinus => write!(f, "--"),
             Token::VBar => write!(f, "|"),
             Token::Hat => write!(f, "^"),
+            Token::Dollar => write!(f, "$"),
+            Token::StartText => write!(f, "\\A"),
+            Token::EndText => write!(f, "\\z"),
+            Token::WordBoundary => write!(f, "\\b"),
+            Token::NotWordBoundary => write!(f, "\\B"),
+            Token::PerlClass(class, negated) => {
+                let c = match class {
+                    PerlClass::Digit => 'd',
+                    PerlClass::Word => 'w',
+                    PerlClass::Space => 's'
+                };
+                write!(f, "\\{}", if *negated { c.to_ascii_uppercase() } else { c })
+            }
+            Token::PosixClass(class, negated) => {
+                let name = POSIX_CLASSES.iter().find(|(_, c)| c == class).unwrap().0;
+                write!(f, "[:{}{}:]", if *negated { "^" } else { "" }, name)
+            }
+            Token::UnicodeClass(name, negated) => write!(f, "\\{}{{{}}}", if *negated { 'P' } else { 'p' }, name),
             Token::Dot => write!(f, "."),
+            Token::Invalid => write!(f, "{}", char::REPLACEMENT_CHARACTER),
             Token::EOF => write!(f, "EOF")
         }
     }
 }
 
 
-pub fn tokenize(s: &String) -> Result<VecDeque<Token>, String> {
+/**
+ * the tokens of a pattern with the byte range each one spans in the pattern
+ */
+pub struct Tokens {
+    pattern: String,
+    tokens: VecDeque<(Token, Range<usize>)>,
+    // the range of the token popped last
+    last: Range<usize>
+}
+
+impl Tokens {
+    pub fn front(&self) -> Option<&Token> {
+        self.tokens.front().map(|(token, _)| token)
+    }
+
+    pub fn pop_front(&mut self) -> Option<Token> {
+        let (token, span) = self.tokens.pop_front()?;
+        self.last = span;
+        Some(token)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    // the range of the next token, or the end of the pattern when no token is left
+    pub fn next_span(&self) -> Range<usize> {
+        self.tokens.front().map_or(self.pattern.len()..self.pattern.len(), |(_, span)| span.clone())
+    }
+
+    pub fn last_span(&self) -> Range<usize> {
+        self.last.clone()
+    }
+
+    pub fn error(&self, kind: fn(Diagnostic) -> Error, span: Range<usize>, message: impl Into<String>) -> Error {
+        kind(Diagnostic::new(&self.pattern, span, message))
+    }
+}
+
+
+/**
+ * an error a helper finds in a construct, with the number of bytes of the helper's input up to the
+ * end of the offending part
+ */
+struct LexError {
+    kind: fn(Diagnostic) -> Error,
+    message: String,
+    len: usize
+}
+
+fn lex_error(kind: fn(Diagnostic) -> Error, message: impl Into<String>, len: usize) -> LexError {
+    LexError {
+        kind,
+        message: message.into(),
+        len
+    }
+}
+
+
+/**
+ * @param s: the pattern right after '{'
+ * @param verbose: whether the `x` flag is set, where whitespace around the counts is ignored as in `{ 2, 3 }`
+ * @return: the repetition token and the number of chars it spans including '}',
+ *          or None if s does not start with `m}`, `m,}` or `m,n}`
+ */
+fn repetition(s: &str, verbose: bool) -> Result<Option<(Token, usize)>, LexError> {
+    let inner = match s.find('}') {
+        Some(end) => &s[..end],
+        None => return Ok(None)
+    };
+    let (min, max) = match inner.split_once(',') {
+        Some((min, max)) => (min, Some(max)),
+        None => (inner, None)
+    };
+    let (min, max) = if verbose { (min.trim(), max.map(str::trim)) } else { (min, max) };
+
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if !is_number(min) || !max.is_none_or(|max| max.is_empty() || is_number(max)) {
+        return Ok(None);
+    }
+
+    let len = inner.len() + 1;
+    let parse_count = |s: &str| s.parse::<u32>().map_err(|_| lex_error(Error::BadRange, format!("repetition count {} is too large", s), len));
+    let min = parse_count(min)?;
+    let max = match max {
+        Some("") => None,
+        Some(max) => Some(parse_count(max)?),
+        None => Some(min)
+    };
+
+    if let Some(max) = max {
+        if min > max {
+            return Err(lex_error(Error::BadRange, format!("invalid repetition {{{},{}}}: the minimum exceeds the maximum", min, max), len));
+        }
+    }
+
+    Ok(Some((Token::Repetition(min, max), inner.chars().count() + 1)))
+}
+
+
+// e.g. `i-s` for setting i and clearing s
+fn display_flags(flags: &[(Flag, bool)]) -> String {
+    let letter = |flag: &Flag| FLAGS.iter().find(|(_, f)| f == flag).unwrap().0;
+    let set = flags.iter().filter(|(_, on)| *on).map(|(flag, _)| letter(flag));
+    let cleared = flags.iter().filter(|(_, on)| !*on).map(|(flag, _)| letter(flag)).collect::<String>();
+
+    if cleared.is_empty() {
+        set.collect()
+    }
+    else {
+        format!("{}-{}", set.collect::<String>(), cleared)
+    }
+}
+
+
+/**
+ * @param s: the pattern right after `(?`
+ * @return: the `(?flags)` or `(?flags:` token and the number of chars it spans including `)` or `:`
+ */
+fn flags(s: &str) -> Result<(Token, usize), LexError> {
+    let mut flags = Vec::new();
+    let mut clearing = false;
+
+    // every char before the one at i is ASCII, so i also counts the chars
+    for (i, c) in s.char_indices() {
+        match c {
+            ')' | ':' if flags.is_empty() => return Err(lex_error(Error::BadGroup, format!("(?{} does not set or clear any flag", &s[..=i]), i + 1)),
+            ')' => return Ok((Token::SetFlags(flags), i + 1)),
+            ':' => return Ok((Token::FlagGroup(flags), i + 1)),
+            '-' if !clearing => clearing = true,
+            '-' => return Err(lex_error(Error::BadGroup, "flags are cleared by more than one -", i + 1)),
+            _ => match FLAGS.iter().find(|(letter, _)| *letter == c) {
+                Some(&(_, flag)) => flags.push((flag, !clearing)),
+                None => return Err(lex_error(Error::BadGroup, format!("unknown flag {} in (?{}", c, &s[..i]), i + c.len_utf8()))
+            }
+        }
+    }
+
+    Err(lex_error(Error::BadGroup, "(? is not closed by ) or :", s.len()))
+}
+
+
+/**
+ * @param s: the pattern right after `(?`
+ * @return: the token opening the group and the number of chars it spans, e.g. `:`, `P<name>` or `i-s:`
+ */
+fn group(s: &str) -> Result<(Token, usize), LexError> {
+    if s.starts_with(':') {
+        return Ok((Token::NonCapturing, 1));
+    }
+
+    let prefix = if s.starts_with("P<") { 2 } else if s.starts_with('<') { 1 } else { 0 };
+    if prefix == 0 {
+        return flags(s);
+    }
+
+    let name = &s[prefix..s.find('>').ok_or_else(|| lex_error(Error::BadGroup, "group name is not closed by >", s.len()))?];
+    if name.is_empty() {
+        return Err(lex_error(Error::BadGroup, "group name is empty", prefix + 1));
+    }
+    else if name.starts_with(|c: char| c.is_ascii_digit()) || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        let message = format!("invalid group name {}: it must consist of word chars not starting with a digit", name);
+        return Err(lex_error(Error::BadGroup, message, prefix + name.len() + 1));
+    }
+
+    Ok((Token::NamedGroup(name.to_string()), prefix + name.chars().count() + 1))
+}
+
+
+/**
+ * @param s: the pattern right after '[' inside brackets
+ * @return: the POSIX class token and the number of chars it spans including ':]',
+ *          or None if s does not start with `:name:]`
+ */
+fn posix_class(s: &str) -> Result<Option<(Token, usize)>, LexError> {
+    let inner = match s.strip_prefix(':').and_then(|s| s.find(":]").map(|end| &s[..end])) {
+        Some(inner) if !inner.is_empty() && inner.chars().all(|c| c.is_ascii_alphabetic() || c == '^') => inner,
+        _ => return Ok(None)
+    };
+    let (name, negated) = match inner.strip_prefix('^') {
+        Some(name) => (name, true),
+        None => (inner, false)
+    };
+
+    match POSIX_CLASSES.iter().find(|(n, _)| *n == name) {
+        Some(&(_, class)) => Ok(Some((Token::PosixClass(class, negated), inner.len() + 3))),
+        None => Err(lex_error(Error::UnknownClass, format!("unknown POSIX class [:{}:]", inner), inner.len() + 3))
+    }
+}
+
+
+/**
+ * @param s: the pattern right after `\p` or `\P`
+ * @param negated: whether the escape is `\P`
+ * @return: the Unicode class token and the number of chars it spans, e.g. `L` or `{Greek}`
+ */
+fn unicode_class(s: &str, negated: bool) -> Result<(Token, usize), LexError> {
+    let (name, len) = if let Some(inner) = s.strip_prefix('{') {
+        let name = &inner[..inner.find('}').ok_or_else(|| lex_error(Error::BadEscape, "\\p{ is not closed by }", s.len()))?];
+        (name, name.chars().count() + 2)
+    }
+    else {
+        let c = s.chars().next().ok_or_else(|| lex_error(Error::BadEscape, "\\p is not followed by any property", 0))?;
+        (&s[..c.len_utf8()], 1)
+    };
+    let (name, negated) = match name.strip_prefix('^') {
+        Some(name) => (name, !negated),
+        None => (name, negated)
+    };
+
+    if property(name).is_none() {
+        let bytes = s.char_indices().nth(len).map_or(s.len(), |(i, _)| i);
+        return Err(lex_error(Error::UnknownClass, format!("unknown Unicode property {}", name), bytes));
+    }
+
+    Ok((Token::UnicodeClass(name.to_string(), negated), len))
+}
+
+
+/**
+ * @param value: the code point an escape stands for, or None if it does not fit in u32
+ * @param escape: the escape as written in the pattern, for the error message
+ * @return: the char of the code point
+ */
+fn code_point(value: Option<u32>, escape: &str) -> Result<char, String> {
+    match value {
+        Some(value @ 0xD800..=0xDFFF) => Err(format!("{} is a surrogate code point U+{:X}, not a char", escape, value)),
+        Some(value) => char::from_u32(value).ok_or_else(|| format!("{} is out of range: U+{:X} exceeds U+10FFFF", escape, value)),
+        None => Err(format!("{} is out of range: it exceeds U+10FFFF", escape))
+    }
+}
+
+
+/**
+ * @param s: the pattern right after `\x`, `\u` or `\o`
+ * @param escape: the escape letter, which determines the radix and the number of digits without braces
+ * @return: the char and the number of chars it spans, e.g. `41` or `{1F600}`
+ */
+fn numeric_escape(s: &str, escape: char) -> Result<(char, usize), LexError> {
+    let (radix, width) = match escape {
+        'x' => (16, 2),
+        'u' => (16, 4),
+        _ => (8, 0)
+    };
+
+    let (digits, len) = if let Some(inner) = s.strip_prefix('{') {
+        let digits = &inner[..inner.find('}').ok_or_else(|| lex_error(Error::BadEscape, format!("\\{}{{ is not closed by }}", escape), s.len()))?];
+        (digits, digits.chars().count() + 2)
+    }
+    else if width == 0 {
+        return Err(lex_error(Error::BadEscape, format!("\\{} is not followed by {{", escape), 0));
+    }
+    else {
+        let len = s.chars().take(width).take_while(|c| c.is_digit(radix)).count();
+        if len < width {
+            return Err(lex_error(Error::BadEscape, format!("\\{} is not followed by {} hexadecimal digits", escape, width), len));
+        }
+        (&s[..len], len)
+    };
+
+    // the bytes of the chars the escape spans after the escape letter
+    let bytes = s.char_indices().nth(len).map_or(s.len(), |(i, _)| i);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(lex_error(Error::BadEscape, format!("invalid digits in \\{}{{{}}}", escape, digits), bytes));
+    }
+
+    let written = format!("\\{}{}", escape, &s[..bytes]);
+    let c = code_point(u32::from_str_radix(digits, radix).ok(), &written).map_err(|message| lex_error(Error::BadEscape, message, bytes))?;
+    Ok((c, len))
+}
+
+
+/**
+ * @param s: the pattern right after `\0`
+ * @return: the char of `\0`, `\0o` or `\0oo` and the number of octal digits following `\0`
+ */
+fn octal_escape(s: &str) -> (char, usize) {
+    let len = s.chars().take(2).take_while(|c| c.is_digit(8)).count();
+    // at most 0o77, so always a valid char
+    (char::from_u32(u32::from_str_radix(&format!("0{}", &s[..len]), 8).unwrap()).unwrap(), len)
+}
+
+
+/**
+ * @param s: the pattern right after `\c`
+ * @return: the control char of `\cX`, e.g. `\cA` is U+0001 and `\c?` is U+007F
+ */
+fn control_escape(s: &str) -> Result<char, LexError> {
+    match s.chars().next() {
+        Some(c) if matches!(c.to_ascii_uppercase(), '?'..='_') => Ok((c.to_ascii_uppercase() as u8 ^ 0x40) as char),
+        Some(c) => Err(lex_error(Error::BadEscape, format!("\\c{} is not a control character", c), c.len_utf8())),
+        None => Err(lex_error(Error::BadEscape, "\\c is not followed by any character", 0))
+    }
+}
+
+
+// the chars that have to be escaped to match literally
+pub const META_CHARACTERS: &str = "\\.+*?()|[]{}^$#&-~";
+
+
+/**
+ * @brief records the error and skips the offending part of the pattern, so that tokenizing goes on after it
+ */
+fn skip_error(error: Error, char_indices: &mut CharIndices, errors: &mut Vec<Error>) {
+    let end = error.span().end;
+    while char_indices.offset() < end {
+        char_indices.next();
+    }
+    errors.push(error);
+}
+
+
+/**
+ * @return: the tokens of the pattern, or the first error found in it
+ */
+pub fn tokenize(s: &str) -> Result<Tokens, Error> {
+    let (tokens, mut errors) = tokenize_all(s);
+
+    if errors.is_empty() {
+        Ok(tokens)
+    }
+    else {
+        Err(errors.swap_remove(0))
+    }
+}
+
+
+/**
+ * @return: the tokens of the whole pattern, with a placeholder standing for each malformed construct,
+ *          and the errors in the order they were found
+ */
+pub fn tokenize_all(s: &str) -> (Tokens, Vec<Error>) {
     let mut ret = VecDeque::<Token>::new();
+    let mut errors = Vec::new();
+    // the byte range of each token in ret, and where the construct read last starts
+    let mut spans = Vec::new();
+    let mut start = 0;
     let mut char_indices = s.char_indices();
+    // the number of brackets the chars are nested in, and the number of tokens before the first item
+    // of the innermost brackets or of the operand after `&&` or `--`
+    let mut class_depth = 0;
+    let mut class_start = 0;
+    // whether the `x` flag is set, and whether it was set outside each open group
+    let mut verbose = false;
+    let mut outer_verbose = Vec::new();
+
+    while let Some((i, c)) = char_indices.next() {
+        // the tokens of the construct read last span up to where this one starts
+        spans.resize(ret.len(), start..i);
+        start = i;
+        // the error a helper reading the pattern from offset finds in the construct starting at i
+        let error = |e: LexError, offset: usize| (e.kind)(Diagnostic::new(s, i..offset + e.len, e.message));
+
+        // in verbose mode, whitespace is ignored and `#` starts a comment to the end of the line,
+        // except inside brackets
+        if verbose && class_depth == 0 {
+            if c.is_whitespace() {
+                continue;
+            }
+            else if c == '#' {
+                char_indices.find(|&(_, c)| c == '\n');
+                continue;
+            }
+        }
+
+        // inside brackets, the meta characters other than `[`, `&&` and `--` are ordinary characters,
+        // and so are `]` and `-` as the first item and `-` as the last item, while `]` right after `&&` or `--` still closes the class
+        if class_depth > 0 && c != '\\' {
+            let at_start = ret.len() == class_start;
+            let token = match c {
+                '[' => match posix_class(char_indices.as_str()) {
+                    Ok(Some((token, len))) => {
+                        char_indices.nth(len - 1);
+                        token
+                    }
+                    // a nested class
+                    Ok(None) => {
+                        class_depth += 1;
+                        ret.push_back(Token::LBracket);
+                        class_start = ret.len();
+                        continue;
+                    }
+                    Err(e) => {
+                        skip_error(error(e, char_indices.offset()), &mut char_indices, &mut errors);
+                        Token::Invalid
+                    }
+                },
+                ']' if !at_start || matches!(ret.back(), Some(Token::ClassAnd | Token::ClassMinus)) => {
+                    class_depth -= 1;
+                    Token::RBracket
+                }
+                '^' if at_start && ret.back() == Some(&Token::LBracket) => {
+                    class_start += 1;
+                    Token::Hat
+                }
+                '&' | '-' if !at_start && char_indices.as_str().starts_with(c) => {
+                    char_indices.next();
+                    ret.push_back(if c == '&' { Token::ClassAnd } else { Token::ClassMinus });
+                    class_start = ret.len();
+                    continue;
+                }
+                '-' if !at_start && !char_indices.as_str().starts_with(']') && !char_indices.as_str().is_empty() => Token::Hyphen,
+                _ => Token::Char(c)
+            };
+
+            ret.push_back(token);
+            continue;
+        }
 
-    while let Some((_i, c)) = char_indices.next() {
         match c {
-            '(' => ret.push_back(Token::LParen),
-            ')' => ret.push_back(Token::RParen),
+            '(' if char_indices.as_str().starts_with('?') => {
+                char_indices.next();
+                let token = match group(char_indices.as_str()) {
+                    Ok((token, len)) => {
+                        char_indices.nth(len - 1);
+                        token
+                    }
+                    // the malformed group still opens a group, unless it runs to the end of the pattern
+                    Err(e) => {
+                        skip_error(error(e, char_indices.offset()), &mut char_indices, &mut errors);
+                        if char_indices.as_str().is_empty() {
+                            continue;
+                        }
+                        Token::NonCapturing
+                    }
+                };
+
+                if !matches!(token, Token::SetFlags(_)) {
+                    outer_verbose.push(verbose);
+                }
+                if let Token::SetFlags(ref flags) | Token::FlagGroup(ref flags) = token {
+                    if let Some(&(_, on)) = flags.iter().find(|(flag, _)| *flag == Flag::Verbose) {
+                        verbose = on;
+                    }
+                }
+
+                ret.push_back(token);
+            }
+            '(' => {
+                outer_verbose.push(verbose);
+                ret.push_back(Token::LParen);
+            }
+            ')' => {
+                verbose = outer_verbose.pop().unwrap_or(verbose);
+                ret.push_back(Token::RParen);
+            }
             '*' => ret.push_back(Token::Asterisk),
+            '+' => ret.push_back(Token::Plus),
+            '?' => ret.push_back(Token::Question),
+            '{' => match repetition(char_indices.as_str(), verbose) {
+                Ok(Some((token, len))) => {
+                    char_indices.nth(len - 1);
+                    ret.push_back(token);
+                }
+                // a brace that does not start a counted repetition is an ordinary character
+                Ok(None) => ret.push_back(Token::Char(c)),
+                // the malformed repetition is dropped, leaving what precedes it unrepeated
+                Err(e) => skip_error(error(e, char_indices.offset()), &mut char_indices, &mut errors)
+            },
             '|' => ret.push_back(Token::VBar),
-            '[' => ret.push_back(Token::LBracket),
-            ']' => ret.push_back(Token::RBracket),
-            '-' => ret.push_back(Token::Hyphen),
+            '[' => {
+                class_depth = 1;
+                ret.push_back(Token::LBracket);
+                class_start = ret.len();
+            }
             '^' => ret.push_back(Token::Hat),
+            '$' => ret.push_back(Token::Dollar),
             '.' => ret.push_back(Token::Dot),
-            '\\' => ret.push_back(Token::Char(match char_indices.next().ok_or("backslash is not followed by any character")?.1 {
-                'n' => '\n', // newline
-                'r' => '\r', // carriage return
-                't' => '\t', // tab character
-                '0' => '\0', // null character
-                _ => c
-            })),
+            '\\' if char_indices.as_str().starts_with('Q') => {
+                char_indices.next();
+                // everything up to `\E` or the end of the pattern is literal
+                let quoted = char_indices.as_str();
+                let (quoted, len) = match quoted.find("\\E") {
+                    Some(end) => (&quoted[..end], quoted[..end].chars().count() + 2),
+                    None => (quoted, quoted.chars().count())
+                };
+
+                ret.extend(quoted.chars().map(Token::Char));
+                if len > 0 {
+                    char_indices.nth(len - 1);
+                }
+            }
+            '\\' if char_indices.as_str().is_empty() => {
+                errors.push(Error::DanglingEscape(Diagnostic::new(s, i..s.len(), "backslash is not followed by any character")));
+            }
+            '\\' => ret.push_back(match char_indices.next().unwrap().1 {
+                'A' => Token::StartText, // start of text
+                'z' => Token::EndText, // end of text
+                'b' => Token::WordBoundary, // word boundary
+                'B' => Token::NotWordBoundary, // not a word boundary
+                'd' => Token::PerlClass(PerlClass::Digit, false), // digit
+                'D' => Token::PerlClass(PerlClass::Digit, true), // not a digit
+                'w' => Token::PerlClass(PerlClass::Word, false), // word character
+                'W' => Token::PerlClass(PerlClass::Word, true), // not a word character
+                's' => Token::PerlClass(PerlClass::Space, false), // whitespace
+                'S' => Token::PerlClass(PerlClass::Space, true), // not a whitespace
+                e @ ('p' | 'P') => match unicode_class(char_indices.as_str(), e == 'P') { // Unicode property
+                    Ok((token, len)) => {
+                        char_indices.nth(len - 1);
+                        token
+                    }
+                    Err(e) => {
+                        skip_error(error(e, char_indices.offset()), &mut char_indices, &mut errors);
+                        Token::Invalid
+                    }
+                },
+                'n' => Token::Char('\n'), // newline
+                'r' => Token::Char('\r'), // carriage return
+                't' => Token::Char('\t'), // tab character
+                '0' => { // null character or octal code point
+                    let (c, len) = octal_escape(char_indices.as_str());
+                    if len > 0 {
+                        char_indices.nth(len - 1);
+                    }
+                    Token::Char(c)
+                }
+                e @ ('x' | 'u' | 'o') => match numeric_escape(char_indices.as_str(), e) { // hexadecimal or octal code point
+                    Ok((c, len)) => {
+                        char_indices.nth(len - 1);
+                        Token::Char(c)
+                    }
+                    Err(e) => {
+                        skip_error(error(e, char_indices.offset()), &mut char_indices, &mut errors);
+                        Token::Invalid
+                    }
+                },
+                'c' => match control_escape(char_indices.as_str()) { // control character
+                    Ok(c) => {
+                        char_indices.next();
+                        Token::Char(c)
+                    }
+                    Err(e) => {
+                        skip_error(error(e, char_indices.offset()), &mut char_indices, &mut errors);
+                        Token::Invalid
+                    }
+                },
+                e if e.is_alphanumeric() => {
+                    errors.push(Error::BadEscape(Diagnostic::new(s, i..char_indices.offset(), format!("unknown escape sequence \\{}", e))));
+                    Token::Invalid
+                }
+                e => Token::Char(e) // escaped meta character
+            }),
             _ => ret.push_back(Token::Char(c))
         }
     }
 
+    spans.resize(ret.len(), start..s.len());
     ret.push_back(Token::EOF);
+    spans.push(s.len()..s.len());
 
-    Ok(ret)
+    let tokens = Tokens {
+        pattern: s.to_string(),
+        tokens: ret.into_iter().zip(spans).collect(),
+        last: 0..0
+    };
+    (tokens, errors)
 }
 
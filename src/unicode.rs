@@ -0,0 +1,57 @@
+pub use crate::unicode_tables::*;
+
+pub const ASCII: &[(char, char)] = &[('\0', '\x7F')];
+
+/**
+ * @param table: sorted, non-overlapping ranges of chars
+ * @return: true iff. one of the ranges contains c
+ */
+pub fn contains(table: &[(char, char)], c: char) -> bool {
+    table.binary_search_by(|&(start, end)| {
+        if end < c {
+            std::cmp::Ordering::Less
+        }
+        else if c < start {
+            std::cmp::Ordering::Greater
+        }
+        else {
+            std::cmp::Ordering::Equal
+        }
+    }).is_ok()
+}
+
+// loose matching of property names, so `\p{Uppercase Letter}` means `\p{uppercase_letter}`
+fn canonical(name: &str) -> String {
+    name.chars().filter(|c| !matches!(c, ' ' | '_' | '-')).flat_map(|c| c.to_lowercase()).collect()
+}
+
+fn lookup(lookup: &[(&str, &'static [(char, char)])], name: &str) -> Option<&'static [(char, char)]> {
+    lookup.iter().find(|(alias, _)| canonical(alias) == canonical(name)).map(|&(_, table)| table)
+}
+
+/**
+ * @param name: a general category, a script or a binary property such as `Lu`, `Greek`, `Script=Hiragana` or `Alphabetic`
+ * @return: the chars having the property
+ */
+pub fn property(name: &str) -> Option<&'static [(char, char)]> {
+    match name.split_once(['=', ':']) {
+        Some((key, value)) => match canonical(key).as_str() {
+            "gc" | "generalcategory" => lookup(GENERAL_CATEGORY, value),
+            "sc" | "script" => lookup(SCRIPT, value),
+            _ => None
+        },
+        None => lookup(GENERAL_CATEGORY, name)
+            .or_else(|| lookup(SCRIPT, name))
+            .or_else(|| lookup(BINARY_PROPERTY, name))
+    }
+}
+
+/**
+ * @return: the other chars with the same simple case folding as c, e.g. 'K' and the Kelvin sign for 'k'
+ */
+pub fn case_folding(c: char) -> &'static [char] {
+    match CASE_FOLDING.binary_search_by_key(&c, |&(c, _)| c) {
+        Ok(i) => CASE_FOLDING[i].1,
+        Err(_) => &[]
+    }
+}